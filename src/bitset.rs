@@ -0,0 +1,276 @@
+//! The bitset backing dense containers.
+//!
+//! A bitset covers all 2**16 values of a chunk with a fixed array of 1024
+//! 64-bit words, and caches its cardinality so that `len` does not need to
+//! count the bits. Set operations work a word at a time.
+
+use std::iter::{self, FromIterator};
+
+pub const WORD_COUNT: usize = 1024;
+
+#[derive(Clone)]
+pub struct BitSet {
+    words: Box<[u64; WORD_COUNT]>,
+    len: usize,
+}
+
+/// Returns the indices of the bits set in `word` in ascending order.
+fn bits(mut word: u64) -> impl Iterator<Item = usize> {
+    iter::from_fn(move || {
+        if word == 0 {
+            return None;
+        }
+        let bit = word.trailing_zeros() as usize;
+        // clear the lowest set bit
+        word &= word - 1;
+        Some(bit)
+    })
+}
+
+impl BitSet {
+    pub fn new() -> BitSet {
+        BitSet {
+            words: Box::new([0; WORD_COUNT]),
+            len: 0,
+        }
+    }
+
+    pub fn from_words(words: Box<[u64; WORD_COUNT]>) -> BitSet {
+        let len = words.iter().map(|word| word.count_ones() as usize).sum();
+        BitSet {
+            words,
+            len,
+        }
+    }
+
+    pub fn words(&self) -> &[u64; WORD_COUNT] {
+        &self.words
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn contains(&self, value: u16) -> bool {
+        let (index, bit) = (value as usize / 64, value % 64);
+        self.words[index] & (1 << bit) != 0
+    }
+
+    pub fn insert(&mut self, value: u16) -> bool {
+        let (index, bit) = (value as usize / 64, value % 64);
+        let word = self.words[index];
+        self.words[index] |= 1 << bit;
+        let inserted = word != self.words[index];
+        self.len += inserted as usize;
+        inserted
+    }
+
+    pub fn remove(&mut self, value: u16) -> bool {
+        let (index, bit) = (value as usize / 64, value % 64);
+        let word = self.words[index];
+        self.words[index] &= !(1 << bit);
+        let removed = word != self.words[index];
+        self.len -= removed as usize;
+        removed
+    }
+
+    /// Returns the values of the bitset in ascending order.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = u16> + 'a {
+        self.words.iter().enumerate().flat_map(|(index, &word)| {
+            bits(word).map(move |bit| (index * 64 + bit) as u16)
+        })
+    }
+
+    pub fn min(&self) -> Option<u16> {
+        self.words.iter()
+            .position(|&word| word != 0)
+            .map(|i| (i * 64 + self.words[i].trailing_zeros() as usize) as u16)
+    }
+
+    pub fn max(&self) -> Option<u16> {
+        self.words.iter()
+            .rposition(|&word| word != 0)
+            .map(|i| (i * 64 + 63 - self.words[i].leading_zeros() as usize) as u16)
+    }
+
+    /// Returns the number of values lower than or equal to `value`.
+    pub fn rank(&self, value: u16) -> usize {
+        let (index, bit) = (value as usize / 64, value % 64);
+        let below = self.words[..index].iter()
+            .map(|word| word.count_ones() as usize)
+            .sum::<usize>();
+        let mask = if bit == 63 { !0 } else { (1 << (bit + 1)) - 1 };
+        below + (self.words[index] & mask).count_ones() as usize
+    }
+
+    /// Returns the number of values in `lo..=hi`, counting only the words
+    /// covering that range.
+    pub fn range_len(&self, lo: u16, hi: u16) -> usize {
+        let (first, last) = (lo as usize / 64, hi as usize / 64);
+        let lo_mask = !0u64 << (lo % 64);
+        let hi_mask = !0u64 >> (63 - hi % 64);
+        if first == last {
+            return (self.words[first] & lo_mask & hi_mask).count_ones() as usize;
+        }
+        let inner = self.words[first + 1..last].iter()
+            .map(|word| word.count_ones() as usize)
+            .sum::<usize>();
+        (self.words[first] & lo_mask).count_ones() as usize + inner +
+            (self.words[last] & hi_mask).count_ones() as usize
+    }
+
+    /// Returns the `n`-th smallest value, counting from zero.
+    pub fn select(&self, n: usize) -> Option<u16> {
+        if n >= self.len {
+            return None;
+        }
+        let mut n = n;
+        for (index, &word) in self.words.iter().enumerate() {
+            let count = word.count_ones() as usize;
+            if n < count {
+                let bit = bits(word).nth(n).unwrap();
+                return Some((index * 64 + bit) as u16);
+            }
+            n -= count;
+        }
+        None
+    }
+
+    pub fn is_subset(&self, other: &BitSet) -> bool {
+        self.len <= other.len &&
+            self.words.iter().zip(other.words.iter()).all(|(&l, &r)| l & !r == 0)
+    }
+
+    pub fn is_disjoint(&self, other: &BitSet) -> bool {
+        self.words.iter().zip(other.words.iter()).all(|(&l, &r)| l & r == 0)
+    }
+
+    /// Returns the number of values present in both `self` and `other`.
+    pub fn intersection_len(&self, other: &BitSet) -> usize {
+        self.words.iter()
+            .zip(other.words.iter())
+            .map(|(&l, &r)| (l & r).count_ones() as usize)
+            .sum()
+    }
+
+    /// Replaces every word of `self` with `op` applied to it and the word of
+    /// `other` at the same index, updating the cardinality. Each operation
+    /// gets its own loop, which the compiler can vectorize.
+    fn combine_with<F: Fn(u64, u64) -> u64>(&mut self, other: &BitSet, op: F) {
+        let mut len = 0;
+        for (lhs, &rhs) in self.words.iter_mut().zip(other.words.iter()) {
+            *lhs = op(*lhs, rhs);
+            len += lhs.count_ones() as usize;
+        }
+        self.len = len;
+    }
+
+    pub fn union_with(&mut self, other: &BitSet) {
+        self.combine_with(other, |l, r| l | r);
+    }
+
+    pub fn intersect_with(&mut self, other: &BitSet) {
+        self.combine_with(other, |l, r| l & r);
+    }
+
+    pub fn difference_with(&mut self, other: &BitSet) {
+        self.combine_with(other, |l, r| l & !r);
+    }
+
+    pub fn symmetric_difference_with(&mut self, other: &BitSet) {
+        self.combine_with(other, |l, r| l ^ r);
+    }
+}
+
+impl FromIterator<u16> for BitSet {
+    fn from_iter<I: IntoIterator<Item = u16>>(values: I) -> BitSet {
+        let mut bitset = BitSet::new();
+        for value in values {
+            bitset.insert(value);
+        }
+        bitset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitSet;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut bitset = BitSet::new();
+        assert_eq!(bitset.len(), 0);
+        assert_eq!(bitset.min(), None);
+        assert_eq!(bitset.max(), None);
+        assert!(bitset.insert(0));
+        assert!(bitset.insert(63));
+        assert!(bitset.insert(64));
+        assert!(bitset.insert(u16::MAX));
+        assert!(!bitset.insert(63));
+        assert_eq!(bitset.len(), 4);
+        assert!(bitset.contains(64));
+        assert!(!bitset.contains(65));
+        assert_eq!(bitset.iter().collect::<Vec<u16>>(), vec![0, 63, 64, u16::MAX]);
+        assert_eq!(bitset.min(), Some(0));
+        assert_eq!(bitset.max(), Some(u16::MAX));
+
+        assert!(bitset.remove(0));
+        assert!(!bitset.remove(0));
+        assert_eq!(bitset.len(), 3);
+        assert_eq!(bitset.min(), Some(63));
+    }
+
+    #[test]
+    fn test_rank_select() {
+        let bitset = (0..u16::MAX).step_by(3).collect::<BitSet>();
+        assert_eq!(bitset.len(), 21845);
+        assert_eq!(bitset.rank(0), 1);
+        assert_eq!(bitset.rank(2), 1);
+        assert_eq!(bitset.rank(63), 22);
+        assert_eq!(bitset.rank(u16::MAX), 21845);
+        assert_eq!(bitset.select(0), Some(0));
+        assert_eq!(bitset.select(21), Some(63));
+        assert_eq!(bitset.select(21844), Some(65532));
+        assert_eq!(bitset.select(21845), None);
+
+        for &(lo, hi) in &[(0, 0), (0, 63), (1, 64), (63, 64), (100, 5000), (0, u16::MAX),
+                           (65472, u16::MAX), (65534, u16::MAX)] {
+            let below = if lo > 0 { bitset.rank(lo - 1) } else { 0 };
+            assert_eq!(bitset.range_len(lo, hi), bitset.rank(hi) - below);
+        }
+        assert_eq!(bitset.range_len(3, 5), 1);
+        assert_eq!(bitset.range_len(4, 5), 0);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = (0..1000).collect::<BitSet>();
+        let b = (500..2000).collect::<BitSet>();
+        assert_eq!(a.intersection_len(&b), 500);
+        assert!(!a.is_disjoint(&b));
+        assert!(!a.is_subset(&b));
+        assert!((600..700).collect::<BitSet>().is_subset(&a));
+
+        let mut union = a.clone();
+        union.union_with(&b);
+        assert_eq!(union.len(), 2000);
+        let mut intersection = a.clone();
+        intersection.intersect_with(&b);
+        assert_eq!(intersection.len(), 500);
+        assert_eq!(intersection.min(), Some(500));
+        let mut difference = a.clone();
+        difference.difference_with(&b);
+        assert_eq!(difference.len(), 500);
+        assert_eq!(difference.max(), Some(499));
+        let mut symmetric_difference = a.clone();
+        symmetric_difference.symmetric_difference_with(&b);
+        assert_eq!(symmetric_difference.len(), 1500);
+        assert!(symmetric_difference.is_disjoint(&intersection));
+
+        let mut words = Box::new([0; 1024]);
+        words[1] = 0b101;
+        let bitset = BitSet::from_words(words);
+        assert_eq!(bitset.len(), 2);
+        assert_eq!(bitset.iter().collect::<Vec<u16>>(), vec![64, 66]);
+    }
+}
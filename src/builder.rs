@@ -0,0 +1,181 @@
+//! Construction of bitmaps with tuned containers.
+
+use {Config, RoaringBitMap};
+
+/// Builds a `RoaringBitMap` whose container conversions and initial
+/// allocations differ from the defaults of `RoaringBitMap::new`.
+///
+/// The settings stay with the bitmap, its clones and the results of set
+/// operations computed from it, but are not serialized.
+///
+/// # Examples
+///
+/// ```
+/// use roaring_bitmap::RoaringBitMapBuilder;
+///
+/// let mut bitmap = RoaringBitMapBuilder::new()
+///     .sparse_limit(1024)
+///     .capacity(16)
+///     .run_containers(false)
+///     .build();
+/// bitmap.insert_range(0..100000);
+///
+/// assert!(!bitmap.run_optimize());
+/// assert_eq!(bitmap.len(), 100000);
+/// ```
+#[derive(Clone, Default)]
+pub struct RoaringBitMapBuilder {
+    config: Config,
+    capacity: usize,
+}
+
+impl RoaringBitMapBuilder {
+    /// Constructs a builder with the default settings.
+    pub fn new() -> RoaringBitMapBuilder {
+        RoaringBitMapBuilder::default()
+    }
+
+    /// Sets how many values a sparse container may hold before it becomes
+    /// dense; dense containers become sparse again below three quarters of
+    /// it. Defaults to 4096.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero or greater than 65536.
+    pub fn sparse_limit(mut self, limit: usize) -> RoaringBitMapBuilder {
+        assert!(limit > 0 && limit <= 1 << 16,
+                "sparse limit must lie in 1..=65536");
+        self.config.sparse_limit = limit;
+        self.config.dense_limit = 3 * limit / 4;
+        self
+    }
+
+    /// Sets the number of containers, each holding the values sharing their
+    /// upper 16 bits, to allocate room for upfront. Defaults to 0.
+    pub fn capacity(mut self, capacity: usize) -> RoaringBitMapBuilder {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the initial capacity of the sparse containers created when a
+    /// value is inserted in an empty chunk. Defaults to 1.
+    pub fn sparse_capacity(mut self, capacity: usize) -> RoaringBitMapBuilder {
+        self.config.sparse_capacity = capacity;
+        self
+    }
+
+    /// Sets whether runs of consecutive values may be stored in run
+    /// containers. When disabled, ranges are stored in sparse or dense
+    /// containers and `run_optimize` leaves the bitmap unchanged. Defaults to
+    /// true.
+    pub fn run_containers(mut self, enabled: bool) -> RoaringBitMapBuilder {
+        self.config.runs = enabled;
+        self
+    }
+
+    /// Constructs an empty bitmap with the configured settings.
+    pub fn build(&self) -> RoaringBitMap {
+        RoaringBitMap {
+            keys: Vec::with_capacity(self.capacity),
+            containers: Vec::with_capacity(self.capacity),
+            config: self.config,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoaringBitMapBuilder;
+    use {Container, RoaringBitMap};
+
+    fn kinds(bitmap: &RoaringBitMap) -> Vec<&'static str> {
+        bitmap.containers.iter()
+            .map(|container| match **container {
+                Container::Dense(_) => "dense",
+                Container::Sparse(_) => "sparse",
+                Container::Run(_) => "run",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sparse_limit() {
+        let mut bitmap = RoaringBitMapBuilder::new().sparse_limit(100).build();
+        for value in 0..99 {
+            bitmap.insert(value * 2);
+            bitmap.validate().unwrap();
+        }
+        assert_eq!(kinds(&bitmap), vec!["sparse"]);
+        bitmap.insert(1000);
+        bitmap.validate().unwrap();
+        assert_eq!(kinds(&bitmap), vec!["dense"]);
+
+        // dense containers are demoted below 75 values
+        for value in 0..25 {
+            bitmap.remove(value * 2);
+            bitmap.validate().unwrap();
+        }
+        assert_eq!(kinds(&bitmap), vec!["dense"]);
+        bitmap.remove(50);
+        bitmap.validate().unwrap();
+        assert_eq!(kinds(&bitmap), vec!["sparse"]);
+
+        let mut single = RoaringBitMapBuilder::new().sparse_limit(1).build();
+        single.insert(7);
+        single.validate().unwrap();
+        assert_eq!(kinds(&single), vec!["dense"]);
+        assert_eq!(single.iter().collect::<Vec<u32>>(), vec![7]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_sparse_limit() {
+        RoaringBitMapBuilder::new().sparse_limit(0);
+    }
+
+    #[test]
+    fn test_run_containers() {
+        let mut bitmap = RoaringBitMapBuilder::new().run_containers(false).build();
+        bitmap.insert_range(0..10);
+        bitmap.insert_range(65536..200000);
+        bitmap.validate().unwrap();
+        assert_eq!(kinds(&bitmap), vec!["sparse", "dense", "dense", "sparse"]);
+        assert!(!bitmap.run_optimize());
+        assert_eq!(bitmap.len(), 10 + 200000 - 65536);
+
+        // containers taken from a bitmap allowing runs are converted
+        let mut runs = RoaringBitMap::new();
+        runs.insert_range(100..200);
+        runs.insert_range(1 << 20..(1 << 20) + 100);
+        bitmap.union_with(&runs);
+        bitmap.validate().unwrap();
+        bitmap.symmetric_difference_with(&runs);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 10 + 200000 - 65536);
+        let union = bitmap.union(&runs);
+        union.validate().unwrap();
+        assert!(!union.containers.iter().any(|c| matches!(**c, Container::Run(_))));
+
+        bitmap.flip_inplace(5..70000);
+        bitmap.validate().unwrap();
+        bitmap.remove_range(..);
+        bitmap.validate().unwrap();
+        assert!(bitmap.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut bitmap = RoaringBitMapBuilder::new()
+            .capacity(8)
+            .sparse_capacity(64)
+            .build();
+        assert!(bitmap.keys.capacity() >= 8);
+        assert!(bitmap.containers.capacity() >= 8);
+        bitmap.insert(3);
+        bitmap.validate().unwrap();
+        match *bitmap.containers[0] {
+            Container::Sparse(ref vec) => assert!(vec.capacity() >= 64),
+            _ => panic!("expected a sparse container"),
+        }
+    }
+}
@@ -0,0 +1,695 @@
+//! Iterators over the values of a `RoaringBitMap`.
+
+use bitset::BitSet;
+use std::cmp;
+
+use {key_val_pair, Container, RoaringBitMap};
+
+/// Position of an iterator within a container.
+///
+/// Values still to be yielded are those in `lo..hi` and, for sparse and run
+/// containers, those stored at the indices `i..j` of the vector.
+#[derive(Clone)]
+struct Cursor {
+    lo: u32,
+    hi: u32,
+    i: usize,
+    j: usize,
+}
+
+impl Cursor {
+    fn new(container: &Container) -> Cursor {
+        let j = match *container {
+            Container::Dense(_) => 0,
+            Container::Sparse(ref vec) => vec.len(),
+            Container::Run(ref runs) => runs.len(),
+        };
+        Cursor { lo: 0, hi: 1 << 16, i: 0, j }
+    }
+
+    fn next(&mut self, container: &Container) -> Option<u16> {
+        match *container {
+            Container::Dense(ref bitset) => {
+                while self.lo < self.hi {
+                    let index = self.lo as usize / 64;
+                    // the bits of the word from `lo` upwards
+                    let word = bitset.words()[index] >> (self.lo % 64);
+                    if word != 0 {
+                        let val = self.lo + word.trailing_zeros();
+                        if val >= self.hi {
+                            self.lo = self.hi;
+                            return None;
+                        }
+                        self.lo = val + 1;
+                        return Some(val as u16);
+                    }
+                    self.lo = cmp::min((index as u32 + 1) * 64, self.hi);
+                }
+                None
+            }
+            Container::Sparse(ref vec) => {
+                if self.i < self.j {
+                    self.i += 1;
+                    Some(vec[self.i - 1])
+                } else {
+                    None
+                }
+            }
+            Container::Run(ref runs) => {
+                while self.i < self.j {
+                    let (start, length) = runs[self.i];
+                    let end = start as u32 + length as u32;
+                    if self.lo <= end {
+                        let val = cmp::max(self.lo, start as u32);
+                        if val >= self.hi {
+                            return None;
+                        }
+                        self.lo = val + 1;
+                        return Some(val as u16);
+                    }
+                    self.i += 1;
+                }
+                None
+            }
+        }
+    }
+
+    fn next_back(&mut self, container: &Container) -> Option<u16> {
+        match *container {
+            Container::Dense(ref bitset) => {
+                while self.lo < self.hi {
+                    let last = self.hi - 1;
+                    let index = last as usize / 64;
+                    // the bits of the word up to `last`, shifted to the top
+                    let word = bitset.words()[index] << (63 - last % 64);
+                    if word != 0 {
+                        let val = last - word.leading_zeros();
+                        if val < self.lo {
+                            self.hi = self.lo;
+                            return None;
+                        }
+                        self.hi = val;
+                        return Some(val as u16);
+                    }
+                    self.hi = cmp::max(index as u32 * 64, self.lo);
+                }
+                None
+            }
+            Container::Sparse(ref vec) => {
+                if self.i < self.j {
+                    self.j -= 1;
+                    Some(vec[self.j])
+                } else {
+                    None
+                }
+            }
+            Container::Run(ref runs) => {
+                while self.i < self.j {
+                    let (start, length) = runs[self.j - 1];
+                    let end = start as u32 + length as u32;
+                    if self.hi > start as u32 {
+                        let val = cmp::min(self.hi - 1, end);
+                        if val < self.lo {
+                            return None;
+                        }
+                        self.hi = val;
+                        return Some(val as u16);
+                    }
+                    self.j -= 1;
+                }
+                None
+            }
+        }
+    }
+
+    /// Skips the values lower than `target`, returning how many were skipped.
+    fn advance_to(&mut self, container: &Container, target: u32) -> usize {
+        match *container {
+            Container::Dense(ref bitset) => {
+                let target = cmp::min(target, self.hi);
+                if target <= self.lo {
+                    return 0;
+                }
+                let skipped = count_below(bitset, target) - count_below(bitset, self.lo);
+                self.lo = target;
+                skipped
+            }
+            Container::Sparse(ref vec) => {
+                let i = self.i + lower_bound(&vec[self.i..self.j], target);
+                let skipped = i - self.i;
+                self.i = i;
+                skipped
+            }
+            Container::Run(ref runs) => {
+                let target = cmp::min(target, self.hi);
+                if target <= self.lo {
+                    return 0;
+                }
+                let runs = &runs[self.i..self.j];
+                // runs ending before `target` are skipped entirely
+                let passed = runs.partition_point(|&(start, length)| {
+                    (start as u32 + length as u32) < target
+                });
+                let last = cmp::min(passed + 1, runs.len());
+                let skipped = runs[..last].iter()
+                    .map(|&run| overlap(run, self.lo, target))
+                    .sum();
+                self.i += passed;
+                self.lo = target;
+                skipped
+            }
+        }
+    }
+
+    /// Skips the values greater than or equal to `target`, returning how
+    /// many were skipped.
+    fn advance_back_to(&mut self, container: &Container, target: u32) -> usize {
+        match *container {
+            Container::Dense(ref bitset) => {
+                let target = cmp::max(target, self.lo);
+                if target >= self.hi {
+                    return 0;
+                }
+                let skipped = count_below(bitset, self.hi) - count_below(bitset, target);
+                self.hi = target;
+                skipped
+            }
+            Container::Sparse(ref vec) => {
+                let j = self.i + lower_bound(&vec[self.i..self.j], target);
+                let skipped = self.j - j;
+                self.j = j;
+                skipped
+            }
+            Container::Run(ref runs) => {
+                let target = cmp::max(target, self.lo);
+                if target >= self.hi {
+                    return 0;
+                }
+                let runs = &runs[self.i..self.j];
+                // runs starting at or after `target` are skipped entirely
+                let kept = runs.partition_point(|&(start, _)| (start as u32) < target);
+                let skipped = runs[kept.saturating_sub(1)..].iter()
+                    .map(|&run| overlap(run, target, self.hi))
+                    .sum();
+                self.j = self.i + kept;
+                self.hi = target;
+                skipped
+            }
+        }
+    }
+}
+
+/// Returns the number of values of the bitset lower than `target`.
+fn count_below(bitset: &BitSet, target: u32) -> usize {
+    if target > 0 {
+        bitset.rank((target - 1) as u16)
+    } else {
+        0
+    }
+}
+
+/// Returns the number of values of the run in `lo..hi`.
+fn overlap((start, length): (u16, u16), lo: u32, hi: u32) -> usize {
+    let from = cmp::max(start as u32, lo);
+    let to = cmp::min(start as u32 + length as u32 + 1, hi);
+    to.saturating_sub(from) as usize
+}
+
+/// Returns the number of values of the sorted slice lower than `target`.
+fn lower_bound(vec: &[u16], target: u32) -> usize {
+    if target > u16::MAX as u32 {
+        return vec.len();
+    }
+    match vec.binary_search(&(target as u16)) {
+        Ok(i) | Err(i) => i,
+    }
+}
+
+/// Iteration state shared by `Iter` and `IntoIter`, generic over whether the
+/// keys and containers are borrowed or owned.
+struct Inner<K, C> {
+    keys: K,
+    containers: C,
+    // index and position of the containers being iterated at each end
+    front: Option<(usize, Cursor)>,
+    back: Option<(usize, Cursor)>,
+    // containers not started from either end yet
+    next_index: usize,
+    next_back_index: usize,
+    len: usize,
+}
+
+impl<K, C> Inner<K, C>
+    where K: AsRef<[u16]>, C: AsRef<[Box<Container>]>
+{
+    fn new(keys: K, containers: C) -> Inner<K, C> {
+        let len = containers.as_ref().iter().map(|c| c.len()).sum();
+        let count = containers.as_ref().len();
+        Inner {
+            keys,
+            containers,
+            front: None,
+            back: None,
+            next_index: 0,
+            next_back_index: count,
+            len,
+        }
+    }
+
+    fn next(&mut self) -> Option<u32> {
+        let keys = self.keys.as_ref();
+        let containers = self.containers.as_ref();
+        loop {
+            if let Some((index, ref mut cursor)) = self.front {
+                if let Some(val) = cursor.next(&containers[index]) {
+                    self.len -= 1;
+                    return Some(((keys[index] as u32) << 16) | val as u32);
+                }
+            }
+            if self.next_index == self.next_back_index {
+                self.front = None;
+                // the only values left are those of the back container
+                if let Some((index, ref mut cursor)) = self.back {
+                    if let Some(val) = cursor.next(&containers[index]) {
+                        self.len -= 1;
+                        return Some(((keys[index] as u32) << 16) | val as u32);
+                    }
+                }
+                return None;
+            }
+            let cursor = Cursor::new(&containers[self.next_index]);
+            self.front = Some((self.next_index, cursor));
+            self.next_index += 1;
+        }
+    }
+
+    fn next_back(&mut self) -> Option<u32> {
+        let keys = self.keys.as_ref();
+        let containers = self.containers.as_ref();
+        loop {
+            if let Some((index, ref mut cursor)) = self.back {
+                if let Some(val) = cursor.next_back(&containers[index]) {
+                    self.len -= 1;
+                    return Some(((keys[index] as u32) << 16) | val as u32);
+                }
+            }
+            if self.next_index == self.next_back_index {
+                self.back = None;
+                // the only values left are those of the front container
+                if let Some((index, ref mut cursor)) = self.front {
+                    if let Some(val) = cursor.next_back(&containers[index]) {
+                        self.len -= 1;
+                        return Some(((keys[index] as u32) << 16) | val as u32);
+                    }
+                }
+                return None;
+            }
+            self.next_back_index -= 1;
+            let cursor = Cursor::new(&containers[self.next_back_index]);
+            self.back = Some((self.next_back_index, cursor));
+        }
+    }
+
+    fn advance_to(&mut self, value: u32) {
+        let (key, val) = key_val_pair(value);
+        let keys = self.keys.as_ref();
+        let containers = self.containers.as_ref();
+        if let Some((index, ref mut cursor)) = self.front {
+            if keys[index] >= key {
+                if keys[index] == key {
+                    self.len -= cursor.advance_to(&containers[index], val as u32);
+                }
+                return;
+            }
+            self.len -= cursor.advance_to(&containers[index], 1 << 16);
+        }
+
+        let unstarted = &keys[self.next_index..self.next_back_index];
+        let skip = match unstarted.binary_search(&key) {
+            Ok(i) | Err(i) => i,
+        };
+        for container in &containers[self.next_index..self.next_index + skip] {
+            self.len -= container.len();
+        }
+        self.next_index += skip;
+        if self.next_index < self.next_back_index {
+            if keys[self.next_index] == key {
+                let container = &containers[self.next_index];
+                let mut cursor = Cursor::new(container);
+                self.len -= cursor.advance_to(container, val as u32);
+                self.front = Some((self.next_index, cursor));
+                self.next_index += 1;
+            }
+            return;
+        }
+
+        // the only values left are those of the back container
+        if let Some((index, ref mut cursor)) = self.back {
+            let target = if keys[index] == key { val as u32 } else { 1 << 16 };
+            if keys[index] <= key {
+                self.len -= cursor.advance_to(&containers[index], target);
+            }
+        }
+    }
+
+    fn advance_back_to(&mut self, value: u32) {
+        let (key, val) = key_val_pair(value);
+        let keys = self.keys.as_ref();
+        let containers = self.containers.as_ref();
+        if let Some((index, ref mut cursor)) = self.back {
+            if keys[index] <= key {
+                if keys[index] == key {
+                    let target = val as u32 + 1;
+                    self.len -= cursor.advance_back_to(&containers[index], target);
+                }
+                return;
+            }
+            self.len -= cursor.advance_back_to(&containers[index], 0);
+        }
+
+        let unstarted = &keys[self.next_index..self.next_back_index];
+        let keep = match unstarted.binary_search(&key) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+        for container in &containers[self.next_index + keep..self.next_back_index] {
+            self.len -= container.len();
+        }
+        self.next_back_index = self.next_index + keep;
+        if self.next_index < self.next_back_index {
+            if keys[self.next_back_index - 1] == key {
+                self.next_back_index -= 1;
+                let container = &containers[self.next_back_index];
+                let mut cursor = Cursor::new(container);
+                self.len -= cursor.advance_back_to(container, val as u32 + 1);
+                self.back = Some((self.next_back_index, cursor));
+            }
+            return;
+        }
+
+        // the only values left are those of the front container
+        if let Some((index, ref mut cursor)) = self.front {
+            let target = if keys[index] == key { val as u32 + 1 } else { 0 };
+            if keys[index] >= key {
+                self.len -= cursor.advance_back_to(&containers[index], target);
+            }
+        }
+    }
+}
+
+/// An iterator over the values of a `RoaringBitMap` in ascending order.
+pub struct Iter<'a> {
+    inner: Inner<&'a [u16], &'a [Box<Container>]>,
+}
+
+/// An owning iterator over the values of a `RoaringBitMap` in ascending
+/// order.
+#[allow(clippy::vec_box)]
+pub struct IntoIter {
+    inner: Inner<Vec<u16>, Vec<Box<Container>>>,
+}
+
+impl<'a> Iter<'a> {
+    /// Advances the iterator from the front so that the next value it yields
+    /// is the smallest one greater than or equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(3);
+    /// bitmap.insert(70000);
+    /// bitmap.insert(80000);
+    ///
+    /// let mut iter = bitmap.iter();
+    /// iter.advance_to(4);
+    /// assert_eq!(iter.next(), Some(70000));
+    /// ```
+    pub fn advance_to(&mut self, value: u32) {
+        self.inner.advance_to(value);
+    }
+
+    /// Advances the iterator from the back so that the next value it yields
+    /// from there is the greatest one lower than or equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(3);
+    /// bitmap.insert(70000);
+    /// bitmap.insert(80000);
+    ///
+    /// let mut iter = bitmap.iter();
+    /// iter.advance_back_to(79999);
+    /// assert_eq!(iter.next_back(), Some(70000));
+    /// ```
+    pub fn advance_back_to(&mut self, value: u32) {
+        self.inner.advance_back_to(value);
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.len, Some(self.inner.len))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<u32> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl IntoIter {
+    /// Advances the iterator from the front so that the next value it yields
+    /// is the smallest one greater than or equal to `value`.
+    pub fn advance_to(&mut self, value: u32) {
+        self.inner.advance_to(value);
+    }
+
+    /// Advances the iterator from the back so that the next value it yields
+    /// from there is the greatest one lower than or equal to `value`.
+    pub fn advance_back_to(&mut self, value: u32) {
+        self.inner.advance_back_to(value);
+    }
+}
+
+impl Iterator for IntoIter {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.len, Some(self.inner.len))
+    }
+}
+
+impl DoubleEndedIterator for IntoIter {
+    fn next_back(&mut self) -> Option<u32> {
+        self.inner.next_back()
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+impl RoaringBitMap {
+    /// Returns an iterator over the values of the bitmap in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(70000);
+    /// bitmap.insert(3);
+    ///
+    /// let values = bitmap.iter().collect::<Vec<u32>>();
+    /// assert_eq!(values, vec![3, 70000]);
+    /// ```
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { inner: Inner::new(&self.keys[..], &self.containers[..]) }
+    }
+}
+
+impl<'a> IntoIterator for &'a RoaringBitMap {
+    type Item = u32;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for RoaringBitMap {
+    type Item = u32;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter { inner: Inner::new(self.keys, self.containers) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {mixed_bitmap, Container, RoaringBitMap};
+
+    #[test]
+    fn test_iter() {
+        let (bitmap, values) = mixed_bitmap();
+        match (&*bitmap.containers[0], &*bitmap.containers[1]) {
+            (Container::Dense(_), Container::Sparse(_)) => (),
+            _ => panic!("expected a dense and a sparse container"),
+        }
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), values);
+        assert_eq!((&bitmap).into_iter().len(), values.len());
+        assert_eq!(bitmap.clone().into_iter().collect::<Vec<u32>>(), values);
+
+        let mut iter = bitmap.iter();
+        assert_eq!(iter.size_hint(), (5019, Some(5019)));
+        iter.next();
+        assert_eq!(iter.len(), 5018);
+        assert_eq!(iter.by_ref().count(), 5018);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.len(), 0);
+
+        assert_eq!(RoaringBitMap::new().iter().next(), None);
+    }
+
+    #[test]
+    fn test_iter_rev() {
+        let (bitmap, values) = mixed_bitmap();
+        let reversed = values.iter().rev().cloned().collect::<Vec<u32>>();
+        assert_eq!(bitmap.iter().rev().collect::<Vec<u32>>(), reversed);
+        assert_eq!(bitmap.clone().into_iter().rev().collect::<Vec<u32>>(),
+                   reversed);
+        assert_eq!(bitmap.iter().rev().take(3).collect::<Vec<u32>>(),
+                   vec![(6 << 16) - 1, (6 << 16) - 2, (6 << 16) - 3]);
+        assert_eq!(RoaringBitMap::new().iter().next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_both_ends() {
+        let (bitmap, values) = mixed_bitmap();
+        let mut iter = bitmap.iter();
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        // alternate ends so that both meet inside each kind of container
+        while let Some(val) = iter.next() {
+            front.push(val);
+            match iter.next_back() {
+                Some(val) => back.push(val),
+                None => break,
+            }
+            assert_eq!(iter.len(), values.len() - front.len() - back.len());
+        }
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+        front.extend(back.into_iter().rev());
+        assert_eq!(front, values);
+
+        for split in &[1, 4000, 5001, 5010, 5018] {
+            let mut iter = bitmap.iter();
+            let head = iter.by_ref().take(*split).collect::<Vec<u32>>();
+            let mut tail = iter.rev().collect::<Vec<u32>>();
+            tail.reverse();
+            assert_eq!(head, &values[..*split]);
+            assert_eq!(tail, &values[*split..]);
+        }
+    }
+
+    #[test]
+    fn test_advance_to() {
+        let (bitmap, values) = mixed_bitmap();
+        let targets = (0..(7 << 16)).step_by(997)
+            .chain(vec![0, 9998, 9999, 65535, 65536, 70001, 131071, 131072])
+            .chain(vec![(5 << 16) + 9, (5 << 16) + 10, (5 << 16) + 65535]);
+        for target in targets {
+            let expected = values.iter()
+                .cloned()
+                .filter(|&v| v >= target)
+                .collect::<Vec<u32>>();
+            let mut iter = bitmap.iter();
+            iter.advance_to(target);
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.collect::<Vec<u32>>(), expected);
+
+            let mut iter = bitmap.clone().into_iter();
+            iter.advance_to(target);
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.next(), expected.first().cloned());
+
+            let expected = values.iter()
+                .cloned()
+                .filter(|&v| v <= target)
+                .collect::<Vec<u32>>();
+            let mut iter = bitmap.iter();
+            iter.advance_back_to(target);
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.collect::<Vec<u32>>(), expected);
+
+            let mut iter = bitmap.clone().into_iter();
+            iter.advance_back_to(target);
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.next_back(), expected.last().cloned());
+        }
+    }
+
+    #[test]
+    fn test_advance_both_ends() {
+        let (bitmap, values) = mixed_bitmap();
+        let mut iter = bitmap.iter();
+        iter.advance_to(5000);
+        iter.advance_back_to((5 << 16) + 65531);
+        assert_eq!(iter.next(), Some(5000));
+        assert_eq!(iter.next_back(), Some((5 << 16) + 65531));
+        // going backwards has no effect
+        iter.advance_to(100);
+        assert_eq!(iter.next(), Some(5002));
+        iter.advance_back_to(u32::MAX);
+        assert_eq!(iter.next_back(), Some((5 << 16) + 65530));
+
+        // both ends within the same containers
+        let ranges = [(10, 20), (70001, 70002), ((5 << 16) + 5, (5 << 16) + 65532)];
+        for &(lo, hi) in &ranges {
+            let expected = values.iter()
+                .cloned()
+                .filter(|&v| v >= lo && v <= hi)
+                .collect::<Vec<u32>>();
+            let mut iter = bitmap.iter();
+            iter.next_back();
+            iter.advance_back_to(hi);
+            iter.advance_to(lo);
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.collect::<Vec<u32>>(), expected);
+
+            let mut iter = bitmap.iter();
+            iter.next();
+            iter.advance_to(lo);
+            iter.advance_back_to(hi);
+            assert_eq!(iter.len(), expected.len());
+            assert_eq!(iter.rev().collect::<Vec<u32>>(),
+                       expected.into_iter().rev().collect::<Vec<u32>>());
+        }
+
+        let mut iter = bitmap.iter();
+        iter.advance_to(10);
+        iter.advance_back_to(9);
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+    }
+}
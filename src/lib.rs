@@ -1,51 +1,640 @@
 //! An implementation of the Roaring Bitmap
 //!
 //! * Samy Chambi, Daniel Lemire, Owen Kaser, Robert Godin,
-//! [Better bitmap performance with Roaring bitmaps]
-//! (http://arxiv.org/abs/1402.6407), in preparation
+//!   [Better bitmap performance with Roaring bitmaps]
+//!   (http://arxiv.org/abs/1402.6407), in preparation
 
-#![feature(iter_arith)]
+#[cfg(feature = "serde")]
+extern crate serde;
 
-extern crate bit_set;
+use bitset::BitSet;
+use std::borrow::Cow;
+use std::cmp::{self, Ordering};
+use std::mem;
+use std::ops::{Bound, RangeBounds};
 
-use bit_set::BitSet;
+pub use builder::RoaringBitMapBuilder;
+pub use iter::{IntoIter, Iter};
+pub use treemap::{RoaringTreemap, TreemapIntoIter, TreemapIter};
+pub use view::{RoaringBitMapView, ViewIter};
+
+mod bitset;
+mod builder;
+mod iter;
+mod ops;
+mod run;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod serialization;
+mod treemap;
+mod view;
 
 const SPARSE_CHUNK_SIZE_LIMIT: usize = 4096;
+// dense containers are only demoted below this many values, so that values
+// inserted and removed around `SPARSE_CHUNK_SIZE_LIMIT` do not convert the
+// container back and forth
+const DENSE_CHUNK_SIZE_LIMIT: usize = 3 * SPARSE_CHUNK_SIZE_LIMIT / 4;
+const DENSE_CHUNK_SIZE_IN_BYTES: usize = 8192;
+
+/// Container tuning of a bitmap, set through `RoaringBitMapBuilder`.
+#[derive(Clone, Copy)]
+struct Config {
+    // sparse containers become dense from this many values upwards
+    sparse_limit: usize,
+    // dense containers become sparse below this many values
+    dense_limit: usize,
+    // initial capacity of the sparse containers created by `insert`
+    sparse_capacity: usize,
+    // whether consecutive values may be stored in run containers
+    runs: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            sparse_limit: SPARSE_CHUNK_SIZE_LIMIT,
+            dense_limit: DENSE_CHUNK_SIZE_LIMIT,
+            sparse_capacity: 1,
+            runs: true,
+        }
+    }
+}
 
 // 8kB
+#[derive(Clone)]
 enum Container {
     // 2**16 bitmap
     Dense(BitSet),
     // no more than 4096 16-bit integers
     Sparse(Vec<u16>),
+    // sorted runs of consecutive 16-bit integers as (start, length) pairs
+    Run(Vec<(u16, u16)>),
 }
 
 impl Container {
-    fn from_sparse_chunk(from: &Vec<u16>) -> Container {
+    fn from_sparse_chunk(from: &[u16]) -> Container {
         Container::Dense(
-            from.iter().map(|&val: &u16| val as usize).collect::<BitSet>()
+            from.iter().cloned().collect::<BitSet>()
         )
     }
 
     fn from_dense_chunk(from: &BitSet) -> Container {
         Container::Sparse(
-            from.iter().map(|val: usize| val as u16).collect::<Vec<u16>>()
+            from.iter().collect::<Vec<u16>>()
         )
     }
 
+    /// Converts runs to a dense or sparse container depending on the
+    /// cardinality.
+    fn from_run_chunk(from: &[(u16, u16)], config: &Config) -> Container {
+        if run::len(from) >= config.sparse_limit {
+            Container::Dense(run::to_bitset(from))
+        } else {
+            Container::Sparse(run::values(from).collect::<Vec<u16>>())
+        }
+    }
+
     fn len(&self) -> usize {
-        match self {
-            &Container::Dense(ref bitset) => bitset.len(),
-            &Container::Sparse(ref vec) => vec.len(),
+        match *self {
+            Container::Dense(ref bitset) => bitset.len(),
+            Container::Sparse(ref vec) => vec.len(),
+            Container::Run(ref runs) => run::len(runs),
         }
     }
 
     fn contains(&self, value: u16) -> bool {
-        match self {
-            &Container::Dense(ref bitset) => bitset.contains(&(value as usize)),
-            &Container::Sparse(ref vec) => vec.binary_search(&value).is_ok(),
+        match *self {
+            Container::Dense(ref bitset) => bitset.contains(value),
+            Container::Sparse(ref vec) => vec.binary_search(&value).is_ok(),
+            Container::Run(ref runs) => run::contains(runs, value),
+        }
+    }
+
+    fn min(&self) -> Option<u16> {
+        match *self {
+            Container::Dense(ref bitset) => bitset.min(),
+            Container::Sparse(ref vec) => vec.first().cloned(),
+            Container::Run(ref runs) => runs.first().map(|&(start, _)| start),
+        }
+    }
+
+    fn max(&self) -> Option<u16> {
+        match *self {
+            Container::Dense(ref bitset) => bitset.max(),
+            Container::Sparse(ref vec) => vec.last().cloned(),
+            Container::Run(ref runs) => {
+                runs.last().map(|&(start, length)| start + length)
+            }
+        }
+    }
+
+    fn insert(&mut self, value: u16, config: &Config) -> bool {
+        let inserted = match *self {
+            Container::Dense(ref mut bitset) => bitset.insert(value),
+            Container::Sparse(ref mut vec) => match vec.binary_search(&value) {
+                Ok(_) => false,
+                Err(i) => {
+                    vec.insert(i, value);
+                    true
+                }
+            },
+            Container::Run(ref mut runs) => run::insert(runs, value),
+        };
+        if inserted {
+            self.normalize(config);
+        }
+        inserted
+    }
+
+    fn remove(&mut self, value: u16, config: &Config) -> bool {
+        let removed = match *self {
+            Container::Dense(ref mut bitset) => bitset.remove(value),
+            Container::Sparse(ref mut vec) => match vec.binary_search(&value) {
+                Ok(i) => {
+                    vec.remove(i);
+                    true
+                }
+                Err(_) => false,
+            },
+            Container::Run(ref mut runs) => run::remove(runs, value),
+        };
+        if removed {
+            self.normalize(config);
+        }
+        removed
+    }
+
+    /// Returns the number of values lower than or equal to `value`.
+    fn rank(&self, value: u16) -> usize {
+        match *self {
+            Container::Dense(ref bitset) => bitset.rank(value),
+            Container::Sparse(ref vec) => match vec.binary_search(&value) {
+                Ok(i) => i + 1,
+                Err(i) => i,
+            },
+            Container::Run(ref runs) => run::rank(runs, value),
+        }
+    }
+
+    /// Returns the number of values in `lo..=hi`.
+    fn range_len(&self, lo: u16, hi: u16) -> usize {
+        if let Container::Dense(ref bitset) = *self {
+            return bitset.range_len(lo, hi);
+        }
+        let below = if lo > 0 { self.rank(lo - 1) } else { 0 };
+        self.rank(hi) - below
+    }
+
+    fn is_subset(&self, other: &Container) -> bool {
+        if self.len() > other.len() {
+            return false;
+        }
+        match (self, other) {
+            (Container::Dense(lhs), Container::Dense(rhs)) => {
+                lhs.is_subset(rhs)
+            }
+            (Container::Dense(lhs), rhs) => {
+                lhs.iter().all(|val| rhs.contains(val))
+            }
+            (Container::Sparse(lhs), rhs) => {
+                lhs.iter().all(|&val| rhs.contains(val))
+            }
+            (Container::Run(lhs), rhs) => {
+                lhs.iter().all(|&(start, length)| {
+                    rhs.range_len(start, start + length) == length as usize + 1
+                })
+            }
+        }
+    }
+
+    fn intersects(&self, other: &Container) -> bool {
+        match (self, other) {
+            (Container::Dense(lhs), Container::Dense(rhs)) => {
+                !lhs.is_disjoint(rhs)
+            }
+            (Container::Sparse(lhs), Container::Sparse(rhs)) => {
+                let (mut i, mut j) = (0, 0);
+                while i < lhs.len() && j < rhs.len() {
+                    match lhs[i].cmp(&rhs[j]) {
+                        Ordering::Less => i += 1,
+                        Ordering::Greater => j += 1,
+                        Ordering::Equal => return true,
+                    }
+                }
+                false
+            }
+            (Container::Run(runs), container) |
+            (container, Container::Run(runs)) => {
+                runs.iter().any(|&(start, length)| {
+                    container.range_len(start, start + length) > 0
+                })
+            }
+            (Container::Sparse(vec), container) |
+            (container, Container::Sparse(vec)) => {
+                vec.iter().any(|&val| container.contains(val))
+            }
+        }
+    }
+
+    /// Returns the number of values present in both `self` and `other`.
+    fn intersection_len(&self, other: &Container) -> usize {
+        match (self, other) {
+            (Container::Dense(lhs), Container::Dense(rhs)) => {
+                lhs.intersection_len(rhs)
+            }
+            (Container::Sparse(lhs), Container::Sparse(rhs)) => {
+                let (mut i, mut j) = (0, 0);
+                let mut len = 0;
+                while i < lhs.len() && j < rhs.len() {
+                    match lhs[i].cmp(&rhs[j]) {
+                        Ordering::Less => i += 1,
+                        Ordering::Greater => j += 1,
+                        Ordering::Equal => {
+                            len += 1;
+                            i += 1;
+                            j += 1;
+                        }
+                    }
+                }
+                len
+            }
+            (Container::Run(runs), container) |
+            (container, Container::Run(runs)) => {
+                runs.iter()
+                    .map(|&(start, length)| {
+                        container.range_len(start, start + length)
+                    })
+                    .sum()
+            }
+            (Container::Sparse(vec), container) |
+            (container, Container::Sparse(vec)) => {
+                vec.iter().filter(|&&val| container.contains(val)).count()
+            }
+        }
+    }
+
+    /// Returns the `n`-th smallest value, counting from zero.
+    fn select(&self, n: usize) -> Option<u16> {
+        match *self {
+            Container::Dense(ref bitset) => bitset.select(n),
+            Container::Sparse(ref vec) => vec.get(n).cloned(),
+            Container::Run(ref runs) => run::select(runs, n),
+        }
+    }
+
+    fn to_bitset<'a>(&'a self) -> Cow<'a, BitSet> {
+        match *self {
+            Container::Dense(ref bitset) => Cow::Borrowed(bitset),
+            Container::Sparse(ref vec) => Cow::Owned(
+                vec.iter().cloned().collect::<BitSet>()
+            ),
+            Container::Run(ref runs) => Cow::Owned(run::to_bitset(runs)),
+        }
+    }
+
+    fn to_runs<'a>(&'a self) -> Cow<'a, [(u16, u16)]> {
+        match *self {
+            Container::Dense(ref bitset) => Cow::Owned(
+                run::from_sorted(bitset.iter())
+            ),
+            Container::Sparse(ref vec) => Cow::Owned(
+                run::from_sorted(vec.iter().cloned())
+            ),
+            Container::Run(ref runs) => Cow::Borrowed(runs),
+        }
+    }
+
+    /// Converts the container to the kind matching its cardinality: sparse
+    /// containers become dense from `config.sparse_limit` values upwards,
+    /// and dense containers become sparse below `config.dense_limit`. Run
+    /// containers are kept only while they are enabled and smaller than
+    /// either.
+    fn normalize(&mut self, config: &Config) {
+        let new_container = match *self {
+            Container::Dense(ref bitset) => {
+                if bitset.len() < config.dense_limit {
+                    Some(Container::from_dense_chunk(bitset))
+                } else {
+                    None
+                }
+            }
+            Container::Sparse(ref vec) => {
+                if vec.len() >= config.sparse_limit {
+                    Some(Container::from_sparse_chunk(vec))
+                } else {
+                    None
+                }
+            }
+            Container::Run(ref runs) => {
+                let size = cmp::min(2 * run::len(runs), DENSE_CHUNK_SIZE_IN_BYTES);
+                if !config.runs || run::size_in_bytes(runs.len()) >= size {
+                    Some(Container::from_run_chunk(runs, config))
+                } else {
+                    None
+                }
+            }
+        };
+        if let Some(c) = new_container {
+            *self = c;
+        }
+    }
+
+    /// Converts the container to a run container if that is the smallest
+    /// representation, and back to a dense or sparse one otherwise. Returns
+    /// whether the container changed.
+    fn run_optimize(&mut self, config: &Config) -> bool {
+        let run_count = match *self {
+            Container::Dense(ref bitset) => {
+                run::count(bitset.iter())
+            }
+            Container::Sparse(ref vec) => run::count(vec.iter().cloned()),
+            Container::Run(ref runs) => runs.len(),
+        };
+        let size = cmp::min(2 * self.len(), DENSE_CHUNK_SIZE_IN_BYTES);
+        let new_container = match *self {
+            Container::Run(ref runs) => {
+                if run::size_in_bytes(run_count) >= size {
+                    Some(Container::from_run_chunk(runs, config))
+                } else {
+                    None
+                }
+            }
+            _ => {
+                if run::size_in_bytes(run_count) < size {
+                    Some(Container::Run(self.to_runs().into_owned()))
+                } else {
+                    None
+                }
+            }
+        };
+        match new_container {
+            Some(c) => {
+                *self = c;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Converts a run container to a dense or sparse one. Returns whether
+    /// the container changed.
+    fn remove_run_compression(&mut self, config: &Config) -> bool {
+        let new_container = match *self {
+            Container::Run(ref runs) => Container::from_run_chunk(runs, config),
+            _ => return false,
+        };
+        *self = new_container;
+        true
+    }
+
+    /// Combines the container with `other` when either of them is a run
+    /// container, keeping the values for which `keep` returns true given
+    /// whether they are present in `self` and in `other`.
+    ///
+    /// Pairings with a dense container are computed on bitsets with
+    /// `bitset_op`, the others on runs.
+    fn combine_with_runs(&mut self, other: &Container,
+                         keep: fn(bool, bool) -> bool,
+                         bitset_op: fn(&mut BitSet, &BitSet), config: &Config) {
+        let new_container = match (&mut *self, other) {
+            (Container::Dense(lhs), rhs) => {
+                bitset_op(lhs, &rhs.to_bitset());
+                None
+            }
+            (lhs, Container::Dense(rhs)) => {
+                let mut bitset = lhs.to_bitset().into_owned();
+                bitset_op(&mut bitset, rhs);
+                Some(Container::Dense(bitset))
+            }
+            (Container::Run(lhs), rhs) => {
+                *lhs = run::combine(lhs, &rhs.to_runs(), keep);
+                None
+            }
+            (lhs, rhs) => {
+                let runs = run::combine(&lhs.to_runs(), &rhs.to_runs(), keep);
+                Some(Container::Run(runs))
+            }
+        };
+        if let Some(c) = new_container {
+            *self = c;
+        }
+        self.normalize(config);
+    }
+
+    fn union_with(&mut self, other: &Container, config: &Config) {
+        let mut new_container: Option<Container> = None;
+        match (&mut *self, other) {
+            (Container::Dense(lhs), Container::Dense(rhs)) => {
+                lhs.union_with(rhs);
+            }
+            (Container::Dense(lhs), Container::Sparse(rhs)) => {
+                for &val in rhs {
+                    lhs.insert(val);
+                }
+            }
+            (Container::Sparse(lhs), Container::Dense(rhs)) => {
+                let mut bitset = rhs.clone();
+                for &val in lhs.iter() {
+                    bitset.insert(val);
+                }
+                new_container = Some(Container::Dense(bitset));
+            }
+            (Container::Sparse(lhs), Container::Sparse(rhs)) => {
+                *lhs = union_sorted(lhs, rhs);
+            }
+            (lhs, rhs) => {
+                lhs.combine_with_runs(rhs, |a, b| a || b, BitSet::union_with, config);
+            }
+        }
+        if let Some(c) = new_container {
+            *self = c;
+        }
+        self.normalize(config);
+    }
+
+    fn intersect_with(&mut self, other: &Container, config: &Config) {
+        let mut new_container: Option<Container> = None;
+        match (&mut *self, other) {
+            (Container::Dense(lhs), Container::Dense(rhs)) => {
+                lhs.intersect_with(rhs);
+            }
+            (Container::Dense(lhs), Container::Sparse(rhs)) => {
+                let vec = rhs.iter()
+                    .cloned()
+                    .filter(|&val| lhs.contains(val))
+                    .collect::<Vec<u16>>();
+                new_container = Some(Container::Sparse(vec));
+            }
+            (Container::Sparse(lhs), Container::Dense(rhs)) => {
+                lhs.retain(|&val| rhs.contains(val));
+            }
+            (Container::Sparse(lhs), Container::Sparse(rhs)) => {
+                *lhs = intersect_sorted(lhs, rhs);
+            }
+            (lhs, rhs) => {
+                lhs.combine_with_runs(rhs, |a, b| a && b, BitSet::intersect_with,
+                                      config);
+            }
+        }
+        if let Some(c) = new_container {
+            *self = c;
+        }
+        self.normalize(config);
+    }
+
+    fn difference_with(&mut self, other: &Container, config: &Config) {
+        match (&mut *self, other) {
+            (Container::Dense(lhs), Container::Dense(rhs)) => {
+                lhs.difference_with(rhs);
+            }
+            (Container::Dense(lhs), Container::Sparse(rhs)) => {
+                for &val in rhs {
+                    lhs.remove(val);
+                }
+            }
+            (Container::Sparse(lhs), Container::Dense(rhs)) => {
+                lhs.retain(|&val| !rhs.contains(val));
+            }
+            (Container::Sparse(lhs), Container::Sparse(rhs)) => {
+                *lhs = difference_sorted(lhs, rhs);
+            }
+            (lhs, rhs) => {
+                lhs.combine_with_runs(rhs, |a, b| a && !b, BitSet::difference_with,
+                                      config);
+            }
+        }
+        self.normalize(config);
+    }
+
+    fn symmetric_difference_with(&mut self, other: &Container, config: &Config) {
+        let mut new_container: Option<Container> = None;
+        match (&mut *self, other) {
+            (Container::Dense(lhs), Container::Dense(rhs)) => {
+                lhs.symmetric_difference_with(rhs);
+            }
+            (Container::Dense(lhs), Container::Sparse(rhs)) => {
+                for &val in rhs {
+                    if !lhs.remove(val) {
+                        lhs.insert(val);
+                    }
+                }
+            }
+            (Container::Sparse(lhs), Container::Dense(rhs)) => {
+                let mut bitset = rhs.clone();
+                for &val in lhs.iter() {
+                    if !bitset.remove(val) {
+                        bitset.insert(val);
+                    }
+                }
+                new_container = Some(Container::Dense(bitset));
+            }
+            (Container::Sparse(lhs), Container::Sparse(rhs)) => {
+                *lhs = symmetric_difference_sorted(lhs, rhs);
+            }
+            (lhs, rhs) => {
+                lhs.combine_with_runs(rhs, |a, b| a != b,
+                                      BitSet::symmetric_difference_with, config);
+            }
+        }
+        if let Some(c) = new_container {
+            *self = c;
+        }
+        self.normalize(config);
+    }
+}
+
+/// Merges two sorted, deduplicated slices into a sorted vector of the values
+/// present in either of them.
+fn union_sorted(lhs: &[u16], rhs: &[u16]) -> Vec<u16> {
+    let mut result = Vec::with_capacity(lhs.len() + rhs.len());
+    let (mut i, mut j) = (0, 0);
+    while i < lhs.len() && j < rhs.len() {
+        match lhs[i].cmp(&rhs[j]) {
+            Ordering::Less => {
+                result.push(lhs[i]);
+                i += 1;
+            }
+            Ordering::Greater => {
+                result.push(rhs[j]);
+                j += 1;
+            }
+            Ordering::Equal => {
+                result.push(lhs[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    result.extend_from_slice(&lhs[i..]);
+    result.extend_from_slice(&rhs[j..]);
+    result
+}
+
+/// Returns a sorted vector of the values present in both of two sorted,
+/// deduplicated slices.
+fn intersect_sorted(lhs: &[u16], rhs: &[u16]) -> Vec<u16> {
+    let mut result = Vec::with_capacity(lhs.len().min(rhs.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < lhs.len() && j < rhs.len() {
+        match lhs[i].cmp(&rhs[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                result.push(lhs[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Returns a sorted vector of the values of `lhs` that are not present in
+/// `rhs`, both being sorted and deduplicated.
+fn difference_sorted(lhs: &[u16], rhs: &[u16]) -> Vec<u16> {
+    let mut result = Vec::with_capacity(lhs.len());
+    let (mut i, mut j) = (0, 0);
+    while i < lhs.len() && j < rhs.len() {
+        match lhs[i].cmp(&rhs[j]) {
+            Ordering::Less => {
+                result.push(lhs[i]);
+                i += 1;
+            }
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    result.extend_from_slice(&lhs[i..]);
+    result
+}
+
+/// Returns a sorted vector of the values present in exactly one of two
+/// sorted, deduplicated slices.
+fn symmetric_difference_sorted(lhs: &[u16], rhs: &[u16]) -> Vec<u16> {
+    let mut result = Vec::with_capacity(lhs.len() + rhs.len());
+    let (mut i, mut j) = (0, 0);
+    while i < lhs.len() && j < rhs.len() {
+        match lhs[i].cmp(&rhs[j]) {
+            Ordering::Less => {
+                result.push(lhs[i]);
+                i += 1;
+            }
+            Ordering::Greater => {
+                result.push(rhs[j]);
+                j += 1;
+            }
+            Ordering::Equal => {
+                i += 1;
+                j += 1;
+            }
         }
     }
+    result.extend_from_slice(&lhs[i..]);
+    result.extend_from_slice(&rhs[j..]);
+    result
 }
 
 #[inline]
@@ -53,9 +642,47 @@ fn key_val_pair(value: u32) -> (u16, u16) {
     ((value >> 16) as u16, (value & ((1 << 16) - 1)) as u16)
 }
 
+/// Converts `range` to inclusive bounds, or `None` if it is empty.
+fn inclusive_bounds<R: RangeBounds<u32>>(range: &R) -> Option<(u32, u32)> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.checked_add(1)?,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end,
+        Bound::Excluded(&end) => end.checked_sub(1)?,
+        Bound::Unbounded => u32::MAX,
+    };
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// Splits the inclusive range `start..=end` into one `(key, lo, hi)` triple
+/// per chunk, `lo..=hi` being the values of that chunk within the range.
+fn chunks(start: u32, end: u32) -> Vec<(u16, u16, u16)> {
+    let (start_key, start_val) = key_val_pair(start);
+    let (end_key, end_val) = key_val_pair(end);
+    (start_key as u32..end_key as u32 + 1)
+        .map(|key| {
+            let key = key as u16;
+            let lo = if key == start_key { start_val } else { 0 };
+            let hi = if key == end_key { end_val } else { u16::MAX };
+            (key, lo, hi)
+        })
+        .collect()
+}
+
+// boxing keeps the containers small to move when keys are inserted
+#[allow(clippy::vec_box)]
+#[derive(Clone, Default)]
 pub struct RoaringBitMap {
     keys: Vec<u16>,
     containers: Vec<Box<Container>>,
+    config: Config,
 }
 
 impl RoaringBitMap {
@@ -69,10 +696,7 @@ impl RoaringBitMap {
     /// let mut bitmap = RoaringBitMap::new();
     /// ```
     pub fn new() -> RoaringBitMap {
-        RoaringBitMap {
-            keys: Vec::new(),
-            containers: Vec::new(),
-        }
+        RoaringBitMap::default()
     }
 
     pub fn len(&self) -> usize {
@@ -96,106 +720,1682 @@ impl RoaringBitMap {
         }
     }
 
-    pub fn insert(&mut self, value: u32) -> bool {
+    /// Returns the number of values lower than or equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(3);
+    /// bitmap.insert(70000);
+    ///
+    /// assert_eq!(bitmap.rank(2), 0);
+    /// assert_eq!(bitmap.rank(3), 1);
+    /// assert_eq!(bitmap.rank(80000), 2);
+    /// ```
+    pub fn rank(&self, value: u32) -> u64 {
         let (key, val) = key_val_pair(value);
-        let mut new_container: Option<(usize, Box<Container>)> = None;
-        let inserted = match self.keys.binary_search(&key) {
-            Ok(i) => match &mut *self.containers[i] {
-                &mut Container::Dense(ref mut bitset) => {
-                    bitset.insert(val as usize)
-                },
-                &mut Container::Sparse(ref mut vec) => {
-                    match vec.binary_search(&val) {
-                        Ok(_) => false,
-                        Err(i) => {
-                            vec.insert(i, val);
-                            if vec.len() == SPARSE_CHUNK_SIZE_LIMIT {
-                                let c = Container::from_sparse_chunk(vec);
-                                new_container = Some((i, Box::new(c)));
-                            }
-                            true
-                        }
-                    }
-                }
-            },
-            Err(i) => {
-                self.keys.insert(i, key);
-                self.containers.insert(i,
-                    Box::new(Container::Sparse(vec![val])));
-                true
-            }
+        let (i, rank) = match self.keys.binary_search(&key) {
+            Ok(i) => (i, self.containers[i].rank(val)),
+            Err(i) => (i, 0),
         };
-        if let Some((i, c)) = new_container {
-            self.containers[i] = c;
-        };
-        inserted
+        let preceding = self.containers[..i].iter().map(|c| c.len() as u64);
+        preceding.sum::<u64>() + rank as u64
     }
 
-    pub fn remove(&mut self, value: u32) -> bool {
-        let (key, val) = key_val_pair(value);
-        let mut new_container: Option<(usize, Box<Container>)> = None;
-        let mut to_remove: Option<usize> = None;
-        let exists = match self.keys.binary_search(&key) {
-            Ok(i) => match &mut *self.containers[i] {
-                &mut Container::Dense(ref mut bitset) => {
-                    let exists = bitset.remove(&(val as usize));
-                    if bitset.len() < SPARSE_CHUNK_SIZE_LIMIT {
-                        let c = Container::from_dense_chunk(bitset);
-                        new_container = Some((i, Box::new(c)));
-                    }
-                    exists
-                }
-                &mut Container::Sparse(ref mut vec) => {
-                    match vec.binary_search(&val) {
-                        Ok(i) => {
-                            vec.remove(i);
-                            if vec.is_empty() {
-                                to_remove = Some(i);
-                            }
-                            true
-                        }
-                        Err(_) => false,
-                    }
-                }
-            },
-            Err(_) => false,
-        };
-        if let Some((i, c)) = new_container {
-            self.containers[i] = c;
-        };
-        if let Some(i) = to_remove {
-            self.keys.remove(i);
-            self.containers.remove(i);
+    /// Returns the number of values in `range`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(10..200000);
+    ///
+    /// assert_eq!(bitmap.range_cardinality(0..20), 10);
+    /// assert_eq!(bitmap.range_cardinality(..), 199990);
+    /// ```
+    pub fn range_cardinality<R: RangeBounds<u32>>(&self, range: R) -> u64 {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return 0,
         };
-        exists
+        let (start_key, start_val) = key_val_pair(start);
+        let (end_key, end_val) = key_val_pair(end);
+        let (first, last) = self.key_range(start, end);
+        let mut len = 0;
+        for i in first..last {
+            let key = self.keys[i];
+            len += if key != start_key && key != end_key {
+                self.containers[i].len()
+            } else {
+                let lo = if key == start_key { start_val } else { 0 };
+                let hi = if key == end_key { end_val } else { u16::MAX };
+                self.containers[i].range_len(lo, hi)
+            } as u64;
+        }
+        len
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::RoaringBitMap;
+    /// Returns true if all values of `range` are present in the bitmap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(10..200000);
+    ///
+    /// assert!(bitmap.contains_range(10..100000));
+    /// assert!(!bitmap.contains_range(9..100000));
+    /// ```
+    pub fn contains_range<R: RangeBounds<u32>>(&self, range: R) -> bool {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return true,
+        };
+        let (first, last) = self.key_range(start, end);
+        let key_count = (end >> 16) - (start >> 16) + 1;
+        if (last - first) as u32 != key_count {
+            return false;
+        }
+        self.range_cardinality(start..=end) == end as u64 - start as u64 + 1
+    }
 
-    #[test]
-    fn test_sparse() {
-        let mut bitmap = RoaringBitMap::new();
-        assert_eq!(bitmap.len(), 0);
-        assert!(bitmap.is_empty());
+    /// Returns true if any value of `range` is present in the bitmap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(100);
+    ///
+    /// assert!(bitmap.intersects_range(50..=100));
+    /// assert!(!bitmap.intersects_range(101..));
+    /// ```
+    pub fn intersects_range<R: RangeBounds<u32>>(&self, range: R) -> bool {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return false,
+        };
+        let (start_key, start_val) = key_val_pair(start);
+        let (end_key, end_val) = key_val_pair(end);
+        let (first, last) = self.key_range(start, end);
+        self.keys[first..last].iter().zip(self.containers[first..last].iter())
+            .any(|(&key, container)| {
+                // containers are never empty
+                if key != start_key && key != end_key {
+                    return true;
+                }
+                let lo = if key == start_key { start_val } else { 0 };
+                let hi = if key == end_key { end_val } else { u16::MAX };
+                container.range_len(lo, hi) > 0
+            })
+    }
+
+    /// Returns the `n`-th smallest value of the bitmap, counting from zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(3);
+    /// bitmap.insert(70000);
+    ///
+    /// assert_eq!(bitmap.select(0), Some(3));
+    /// assert_eq!(bitmap.select(1), Some(70000));
+    /// assert_eq!(bitmap.select(2), None);
+    /// ```
+    pub fn select(&self, n: u64) -> Option<u32> {
+        let mut n = n;
+        for (&key, container) in self.keys.iter().zip(self.containers.iter()) {
+            let len = container.len() as u64;
+            if n < len {
+                return container.select(n as usize)
+                    .map(|val| ((key as u32) << 16) | val as u32);
+            }
+            n -= len;
+        }
+        None
+    }
+
+    /// Returns the smallest value of the bitmap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// assert_eq!(bitmap.min(), None);
+    /// bitmap.insert(70000);
+    /// bitmap.insert(3);
+    /// assert_eq!(bitmap.min(), Some(3));
+    /// ```
+    pub fn min(&self) -> Option<u32> {
+        match (self.keys.first(), self.containers.first()) {
+            (Some(&key), Some(container)) => {
+                container.min().map(|val| ((key as u32) << 16) | val as u32)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the greatest value of the bitmap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// assert_eq!(bitmap.max(), None);
+    /// bitmap.insert(70000);
+    /// bitmap.insert(3);
+    /// assert_eq!(bitmap.max(), Some(70000));
+    /// ```
+    pub fn max(&self) -> Option<u32> {
+        match (self.keys.last(), self.containers.last()) {
+            (Some(&key), Some(container)) => {
+                container.max().map(|val| ((key as u32) << 16) | val as u32)
+            }
+            _ => None,
+        }
+    }
+
+    /// Removes and returns the smallest value of the bitmap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(70000);
+    /// bitmap.insert(3);
+    /// assert_eq!(bitmap.pop_min(), Some(3));
+    /// assert_eq!(bitmap.pop_min(), Some(70000));
+    /// assert_eq!(bitmap.pop_min(), None);
+    /// ```
+    pub fn pop_min(&mut self) -> Option<u32> {
+        let value = self.min()?;
+        self.remove_from_container(0, value as u16);
+        Some(value)
+    }
+
+    /// Removes and returns the greatest value of the bitmap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(70000);
+    /// bitmap.insert(3);
+    /// assert_eq!(bitmap.pop_max(), Some(70000));
+    /// assert_eq!(bitmap.pop_max(), Some(3));
+    /// assert_eq!(bitmap.pop_max(), None);
+    /// ```
+    pub fn pop_max(&mut self) -> Option<u32> {
+        let value = self.max()?;
+        let i = self.containers.len() - 1;
+        self.remove_from_container(i, value as u16);
+        Some(value)
+    }
+
+    /// Removes `val` from the `i`-th container, dropping the container when
+    /// it becomes empty.
+    fn remove_from_container(&mut self, i: usize, val: u16) -> bool {
+        let removed = self.containers[i].remove(val, &self.config);
+        if self.containers[i].len() == 0 {
+            self.keys.remove(i);
+            self.containers.remove(i);
+        }
+        removed
+    }
+
+    pub fn insert(&mut self, value: u32) -> bool {
+        let (key, val) = key_val_pair(value);
+        match self.keys.binary_search(&key) {
+            Ok(i) => self.containers[i].insert(val, &self.config),
+            Err(i) => {
+                let vec = Vec::with_capacity(self.config.sparse_capacity);
+                let mut container = Box::new(Container::Sparse(vec));
+                container.insert(val, &self.config);
+                self.keys.insert(i, key);
+                self.containers.insert(i, container);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, value: u32) -> bool {
+        let (key, val) = key_val_pair(value);
+        match self.keys.binary_search(&key) {
+            Ok(i) => self.remove_from_container(i, val),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the indices of the containers whose keys lie in `start..=end`.
+    fn key_range(&self, start: u32, end: u32) -> (usize, usize) {
+        let (start_key, _) = key_val_pair(start);
+        let (end_key, _) = key_val_pair(end);
+        let first = match self.keys.binary_search(&start_key) {
+            Ok(i) | Err(i) => i,
+        };
+        let last = match self.keys.binary_search(&end_key) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+        (first, last)
+    }
+
+    /// Applies `op` to the containers of the chunks overlapping with the
+    /// inclusive range `start..=end`, along with the bounds of the range
+    /// within each chunk and the configuration of the bitmap. Missing
+    /// containers are created empty beforehand if `create_missing` is set, and
+    /// containers left empty are dropped.
+    fn update_range<F>(&mut self, start: u32, end: u32, create_missing: bool,
+                       mut op: F)
+        where F: FnMut(&mut Container, u16, u16, &Config)
+    {
+        let (first, last) = self.key_range(start, end);
+        let mut old = self.keys.drain(first..last)
+            .zip(self.containers.drain(first..last))
+            .peekable();
+        let mut keys = Vec::new();
+        let mut containers = Vec::new();
+        for (key, lo, hi) in chunks(start, end) {
+            let mut container = match old.peek() {
+                Some(&(k, _)) if k == key => old.next().unwrap().1,
+                _ if create_missing => Box::new(Container::Sparse(Vec::new())),
+                _ => continue,
+            };
+            op(&mut container, lo, hi, &self.config);
+            if container.len() > 0 {
+                keys.push(key);
+                containers.push(container);
+            }
+        }
+        drop(old);
+        self.keys.splice(first..first, keys);
+        self.containers.splice(first..first, containers);
+    }
+
+    /// Inserts all values of `range`, returning how many were not already
+    /// present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(5);
+    ///
+    /// assert_eq!(bitmap.insert_range(0..100000), 99999);
+    /// assert_eq!(bitmap.len(), 100000);
+    /// assert_eq!(bitmap.insert_range(..), (1 << 32) - 100000);
+    /// ```
+    pub fn insert_range<R: RangeBounds<u32>>(&mut self, range: R) -> u64 {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return 0,
+        };
+        let mut inserted = 0;
+        self.update_range(start, end, true, |container, lo, hi, config| {
+            let len = container.len();
+            let range_container = Container::Run(vec![(lo, hi - lo)]);
+            if lo == 0 && hi == u16::MAX {
+                *container = range_container;
+                container.normalize(config);
+            } else {
+                container.union_with(&range_container, config);
+            }
+            inserted += (container.len() - len) as u64;
+        });
+        inserted
+    }
+
+    /// Removes all values of `range`, returning how many were present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(0..100000);
+    ///
+    /// assert_eq!(bitmap.remove_range(10..=19), 10);
+    /// assert_eq!(bitmap.remove_range(99990..), 10);
+    /// assert_eq!(bitmap.len(), 99980);
+    /// ```
+    pub fn remove_range<R: RangeBounds<u32>>(&mut self, range: R) -> u64 {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return 0,
+        };
+        let mut removed = 0;
+        self.update_range(start, end, false, |container, lo, hi, config| {
+            let len = container.len();
+            if lo == 0 && hi == u16::MAX {
+                *container = Container::Sparse(Vec::new());
+            } else {
+                let range_container = Container::Run(vec![(lo, hi - lo)]);
+                container.difference_with(&range_container, config);
+            }
+            removed += (len - container.len()) as u64;
+        });
+        removed
+    }
+
+    /// Toggles every value of `range`: values present in the bitmap are
+    /// removed and the missing ones are inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(0..10);
+    ///
+    /// bitmap.flip_inplace(5..15);
+    /// assert_eq!(bitmap.len(), 10);
+    /// assert!(bitmap.contains(4));
+    /// assert!(!bitmap.contains(5));
+    /// assert!(bitmap.contains(14));
+    /// ```
+    pub fn flip_inplace<R: RangeBounds<u32>>(&mut self, range: R) {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        self.update_range(start, end, true, |container, lo, hi, config| {
+            let range_container = Container::Run(vec![(lo, hi - lo)]);
+            container.symmetric_difference_with(&range_container, config);
+        });
+    }
+
+    /// Returns a new bitmap with every value of `range` toggled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(3);
+    ///
+    /// let flipped = bitmap.flip(0..10);
+    /// assert_eq!(flipped.len(), 9);
+    /// assert!(!flipped.contains(3));
+    /// ```
+    pub fn flip<R: RangeBounds<u32>>(&self, range: R) -> RoaringBitMap {
+        let mut result = self.clone();
+        result.flip_inplace(range);
+        result
+    }
+
+    /// Adds all values of `other` to `self`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(70000);
+    ///
+    /// a.union_with(&b);
+    /// assert!(a.contains(1));
+    /// assert!(a.contains(70000));
+    /// ```
+    pub fn union_with(&mut self, other: &RoaringBitMap) {
+        self.merge_with(other, true, true, Container::union_with);
+    }
+
+    /// Returns a new bitmap holding the values present in `self` or `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(2);
+    ///
+    /// let c = a.union(&b);
+    /// assert_eq!(c.len(), 2);
+    /// ```
+    pub fn union(&self, other: &RoaringBitMap) -> RoaringBitMap {
+        let mut result = self.clone();
+        result.union_with(other);
+        result
+    }
+
+    /// Removes all values of `self` that are not present in `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(70000);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(1);
+    ///
+    /// a.intersect_with(&b);
+    /// assert!(a.contains(1));
+    /// assert!(!a.contains(70000));
+    /// ```
+    pub fn intersect_with(&mut self, other: &RoaringBitMap) {
+        self.merge_with(other, false, false, Container::intersect_with);
+    }
+
+    /// Returns a new bitmap holding the values present in both `self` and
+    /// `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(2);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(2);
+    ///
+    /// let c = a.intersection(&b);
+    /// assert_eq!(c.len(), 1);
+    /// assert!(c.contains(2));
+    /// ```
+    pub fn intersection(&self, other: &RoaringBitMap) -> RoaringBitMap {
+        let mut result = RoaringBitMap {
+            keys: Vec::new(),
+            containers: Vec::new(),
+            config: self.config,
+        };
+        let (mut i, mut j) = (0, 0);
+        while i < self.keys.len() && j < other.keys.len() {
+            match self.keys[i].cmp(&other.keys[j]) {
+                Ordering::Less => i += 1,
+                Ordering::Greater => j += 1,
+                Ordering::Equal => {
+                    let mut container = self.containers[i].clone();
+                    container.intersect_with(&other.containers[j], &self.config);
+                    if container.len() > 0 {
+                        result.keys.push(self.keys[i]);
+                        result.containers.push(container);
+                    }
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        result
+    }
+
+    /// Removes all values of `other` from `self`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(2);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(2);
+    ///
+    /// a.difference_with(&b);
+    /// assert!(a.contains(1));
+    /// assert!(!a.contains(2));
+    /// ```
+    pub fn difference_with(&mut self, other: &RoaringBitMap) {
+        self.merge_with(other, true, false, Container::difference_with);
+    }
+
+    /// Returns a new bitmap holding the values present in `self` but not in
+    /// `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(2);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(2);
+    /// b.insert(3);
+    ///
+    /// let c = a.difference(&b);
+    /// assert_eq!(c.len(), 1);
+    /// assert!(c.contains(1));
+    /// ```
+    pub fn difference(&self, other: &RoaringBitMap) -> RoaringBitMap {
+        let mut result = self.clone();
+        result.difference_with(other);
+        result
+    }
+
+    /// Keeps the values present in exactly one of `self` and `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(2);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(2);
+    /// b.insert(3);
+    ///
+    /// a.symmetric_difference_with(&b);
+    /// assert!(a.contains(1));
+    /// assert!(!a.contains(2));
+    /// assert!(a.contains(3));
+    /// ```
+    pub fn symmetric_difference_with(&mut self, other: &RoaringBitMap) {
+        self.merge_with(other, true, true, Container::symmetric_difference_with);
+    }
+
+    /// Returns a new bitmap holding the values present in exactly one of
+    /// `self` and `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(2);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(2);
+    /// b.insert(3);
+    ///
+    /// let c = a.symmetric_difference(&b);
+    /// assert_eq!(c.len(), 2);
+    /// ```
+    pub fn symmetric_difference(&self, other: &RoaringBitMap) -> RoaringBitMap {
+        let mut result = self.clone();
+        result.symmetric_difference_with(other);
+        result
+    }
+
+    /// Returns true if all values of `self` are present in `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(1);
+    /// b.insert(70000);
+    ///
+    /// assert!(a.is_subset(&b));
+    /// assert!(!b.is_subset(&a));
+    /// ```
+    pub fn is_subset(&self, other: &RoaringBitMap) -> bool {
+        if self.keys.len() > other.keys.len() {
+            return false;
+        }
+        let mut j = 0;
+        for (&key, container) in self.keys.iter().zip(self.containers.iter()) {
+            while j < other.keys.len() && other.keys[j] < key {
+                j += 1;
+            }
+            if j == other.keys.len() || other.keys[j] != key {
+                return false;
+            }
+            if !container.is_subset(&other.containers[j]) {
+                return false;
+            }
+            j += 1;
+        }
+        true
+    }
+
+    /// Returns true if all values of `other` are present in `self`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(70000);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(1);
+    ///
+    /// assert!(a.is_superset(&b));
+    /// assert!(!b.is_superset(&a));
+    /// ```
+    pub fn is_superset(&self, other: &RoaringBitMap) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Returns true if `self` and `other` have at least one value in common.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// a.insert(70000);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(70000);
+    ///
+    /// assert!(a.intersects(&b));
+    /// b.remove(70000);
+    /// assert!(!a.intersects(&b));
+    /// ```
+    pub fn intersects(&self, other: &RoaringBitMap) -> bool {
+        let (mut i, mut j) = (0, 0);
+        while i < self.keys.len() && j < other.keys.len() {
+            match self.keys[i].cmp(&other.keys[j]) {
+                Ordering::Less => i += 1,
+                Ordering::Greater => j += 1,
+                Ordering::Equal => {
+                    if self.containers[i].intersects(&other.containers[j]) {
+                        return true;
+                    }
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns true if `self` and `other` have no value in common.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert(1);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert(2);
+    ///
+    /// assert!(a.is_disjoint(&b));
+    /// ```
+    pub fn is_disjoint(&self, other: &RoaringBitMap) -> bool {
+        !self.intersects(other)
+    }
+
+    /// Returns the number of values present in both `self` and `other`,
+    /// without building their intersection.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert_range(0..100);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert_range(50..150);
+    ///
+    /// assert_eq!(a.intersection_len(&b), 50);
+    /// ```
+    pub fn intersection_len(&self, other: &RoaringBitMap) -> u64 {
+        let (mut i, mut j) = (0, 0);
+        let mut len = 0;
+        while i < self.keys.len() && j < other.keys.len() {
+            match self.keys[i].cmp(&other.keys[j]) {
+                Ordering::Less => i += 1,
+                Ordering::Greater => j += 1,
+                Ordering::Equal => {
+                    let container = &self.containers[i];
+                    len += container.intersection_len(&other.containers[j]) as u64;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        len
+    }
+
+    /// Returns the number of values present in `self` or `other`, without
+    /// building their union.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert_range(0..100);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert_range(50..150);
+    ///
+    /// assert_eq!(a.union_len(&b), 150);
+    /// ```
+    pub fn union_len(&self, other: &RoaringBitMap) -> u64 {
+        self.len() as u64 + other.len() as u64 - self.intersection_len(other)
+    }
+
+    /// Returns the number of values present in `self` but not in `other`,
+    /// without building their difference.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert_range(0..100);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert_range(50..150);
+    ///
+    /// assert_eq!(a.difference_len(&b), 50);
+    /// ```
+    pub fn difference_len(&self, other: &RoaringBitMap) -> u64 {
+        self.len() as u64 - self.intersection_len(other)
+    }
+
+    /// Returns the number of values present in exactly one of `self` and
+    /// `other`, without building their symmetric difference.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert_range(0..100);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert_range(50..150);
+    ///
+    /// assert_eq!(a.symmetric_difference_len(&b), 100);
+    /// ```
+    pub fn symmetric_difference_len(&self, other: &RoaringBitMap) -> u64 {
+        self.len() as u64 + other.len() as u64 - 2 * self.intersection_len(other)
+    }
+
+    /// Returns the Jaccard index of `self` and `other`, that is the size of
+    /// their intersection divided by the size of their union. Two empty
+    /// bitmaps have an index of 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert_range(0..100);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert_range(50..150);
+    ///
+    /// assert_eq!(a.jaccard_index(&b), 1.0 / 3.0);
+    /// ```
+    pub fn jaccard_index(&self, other: &RoaringBitMap) -> f64 {
+        let intersection_len = self.intersection_len(other);
+        let union_len = self.len() as u64 + other.len() as u64 - intersection_len;
+        if union_len == 0 {
+            1.0
+        } else {
+            intersection_len as f64 / union_len as f64
+        }
+    }
+
+    /// Converts every container to the smallest of the run, sparse and dense
+    /// representations. Returns whether any container changed; this is never
+    /// the case when run containers were disabled through
+    /// `RoaringBitMapBuilder::run_containers`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// for value in 0..1000 {
+    ///     bitmap.insert(value);
+    /// }
+    ///
+    /// assert!(bitmap.run_optimize());
+    /// assert!(!bitmap.run_optimize());
+    /// assert_eq!(bitmap.len(), 1000);
+    /// ```
+    pub fn run_optimize(&mut self) -> bool {
+        if !self.config.runs {
+            return false;
+        }
+        let mut changed = false;
+        for container in &mut self.containers {
+            changed |= container.run_optimize(&self.config);
+        }
+        changed
+    }
+
+    /// Converts every run container back to a sparse or dense one. Returns
+    /// whether any container changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// for value in 0..1000 {
+    ///     bitmap.insert(value);
+    /// }
+    /// bitmap.run_optimize();
+    ///
+    /// assert!(bitmap.remove_run_compression());
+    /// assert!(!bitmap.remove_run_compression());
+    /// assert_eq!(bitmap.len(), 1000);
+    /// ```
+    pub fn remove_run_compression(&mut self) -> bool {
+        let mut changed = false;
+        for container in &mut self.containers {
+            changed |= container.remove_run_compression(&self.config);
+        }
+        changed
+    }
+
+    /// Checks the internal invariants of the bitmap: keys are sorted and
+    /// unique, each key has exactly one non-empty container, sparse and dense
+    /// containers match their cardinality and run containers hold sorted,
+    /// non-adjacent runs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(0..5000);
+    /// bitmap.remove(70);
+    /// assert!(bitmap.validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keys.len() != self.containers.len() {
+            return Err(format!("{} keys but {} containers",
+                               self.keys.len(), self.containers.len()));
+        }
+        for (i, window) in self.keys.windows(2).enumerate() {
+            if window[0] >= window[1] {
+                return Err(format!("keys are not sorted at index {}", i + 1));
+            }
+        }
+        for (&key, container) in self.keys.iter().zip(self.containers.iter()) {
+            let len = container.len();
+            if len == 0 {
+                return Err(format!("container {} is empty", key));
+            }
+            match **container {
+                Container::Dense(_) if len < self.config.dense_limit => {
+                    return Err(format!("dense container {} holds only {} values",
+                                       key, len));
+                }
+                Container::Sparse(ref vec) => {
+                    if len >= self.config.sparse_limit {
+                        return Err(format!("sparse container {} holds {} values",
+                                           key, len));
+                    }
+                    if vec.windows(2).any(|window| window[0] >= window[1]) {
+                        return Err(format!("sparse container {} is not sorted", key));
+                    }
+                }
+                Container::Run(_) if !self.config.runs => {
+                    return Err(format!("run container {} but runs are disabled",
+                                       key));
+                }
+                Container::Run(ref runs) => {
+                    let overflows = |&(start, length): &(u16, u16)| {
+                        start.checked_add(length).is_none()
+                    };
+                    if runs.iter().any(overflows) {
+                        return Err(format!("run container {} overflows", key));
+                    }
+                    let sorted = runs.windows(2).all(|window| {
+                        let (start, length) = window[0];
+                        start as u32 + length as u32 + 1 < window[1].0 as u32
+                    });
+                    if !sorted {
+                        return Err(format!("run container {} is not sorted", key));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks the keys of `self` and `other` in lockstep, rebuilding `self`.
+    ///
+    /// Containers whose key only appears in `self` (resp. `other`) are kept
+    /// (resp. cloned in) according to `keep_lhs` (resp. `keep_rhs`), and
+    /// containers sharing a key are combined with `op` under the
+    /// configuration of `self`. Containers left empty are dropped.
+    fn merge_with<F>(&mut self, other: &RoaringBitMap,
+                     keep_lhs: bool, keep_rhs: bool, mut op: F)
+        where F: FnMut(&mut Container, &Container, &Config)
+    {
+        let keys = mem::take(&mut self.keys);
+        let containers = mem::take(&mut self.containers);
+        let mut lhs = keys.into_iter().zip(containers).peekable();
+        let mut rhs = other.keys.iter().zip(other.containers.iter()).peekable();
+        loop {
+            let order = match (lhs.peek(), rhs.peek()) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(&(k1, _)), Some(&(&k2, _))) => k1.cmp(&k2),
+            };
+            match order {
+                Ordering::Less => {
+                    let (key, container) = lhs.next().unwrap();
+                    if keep_lhs {
+                        self.keys.push(key);
+                        self.containers.push(container);
+                    }
+                }
+                Ordering::Greater => {
+                    let (&key, container) = rhs.next().unwrap();
+                    if keep_rhs {
+                        // `other` may have been built with another configuration
+                        let mut container = container.clone();
+                        container.normalize(&self.config);
+                        self.keys.push(key);
+                        self.containers.push(container);
+                    }
+                }
+                Ordering::Equal => {
+                    let (key, mut container) = lhs.next().unwrap();
+                    let (_, other_container) = rhs.next().unwrap();
+                    op(&mut container, other_container, &self.config);
+                    if container.len() > 0 {
+                        self.keys.push(key);
+                        self.containers.push(container);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Builds a bitmap by inserting `values` one at a time, validating it after
+/// every insertion.
+#[cfg(test)]
+fn from_values<I: IntoIterator<Item = u32>>(values: I) -> RoaringBitMap {
+    let mut bitmap = RoaringBitMap::new();
+    for value in values {
+        bitmap.insert(value);
+        bitmap.validate().unwrap();
+    }
+    bitmap
+}
+
+/// Builds a bitmap holding a single run container at `key`.
+#[cfg(test)]
+fn from_runs_at(key: u16, runs: Vec<(u16, u16)>) -> RoaringBitMap {
+    RoaringBitMap {
+        keys: vec![key],
+        containers: vec![Box::new(Container::Run(runs))],
+        config: Config::default(),
+    }
+}
+
+/// Returns a bitmap holding a dense, a sparse and a run container, along
+/// with its values in ascending order.
+#[cfg(test)]
+fn mixed_bitmap() -> (RoaringBitMap, Vec<u32>) {
+    let mut bitmap = from_values((0..5000).map(|v| v * 2)
+        .chain(vec![70000, 70002, 131071]));
+    let runs = from_runs_at(5, vec![(0, 9), (65530, 5)]);
+    bitmap.keys.extend(runs.keys);
+    bitmap.containers.extend(runs.containers);
+    bitmap.validate().unwrap();
+    let values = (0..5000).map(|v| v * 2)
+        .chain(vec![70000, 70002, 131071])
+        .chain((5 << 16)..(5 << 16) + 10)
+        .chain((5 << 16) + 65530..(6 << 16))
+        .collect();
+    (bitmap, values)
+}
+
+/// Returns bitmaps pairing every kind of container with the others: dense,
+/// sparse, small runs, runs as large as a dense container, another dense
+/// bitmap and an empty one.
+#[cfg(test)]
+fn mixed_bitmaps() -> Vec<RoaringBitMap> {
+    let mut dense = from_values((0..5000).map(|v| v * 2));
+    dense.insert_range(3 << 16..4 << 16);
+    dense.validate().unwrap();
+    let mut sparse = from_values(vec![2, 3, 4, 6, 100000]);
+    sparse.insert_range(3 << 16..(3 << 16) + 3);
+    sparse.validate().unwrap();
+    let mut runs = from_runs_at(0, vec![(0, 9)]);
+    runs.insert_range((3 << 16) + 5..(3 << 16) + 100);
+    runs.validate().unwrap();
+    let dense_runs = from_runs_at(0, vec![(5, 8000)]);
+    let other_dense = from_values((0..5000).map(|v| v * 3));
+    vec![dense, sparse, runs, dense_runs, other_dense, RoaringBitMap::new()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_runs_at, from_values, mixed_bitmap, mixed_bitmaps, Container,
+                RoaringBitMap};
+
+    fn assert_same_chunk(lhs: &RoaringBitMap, rhs: &RoaringBitMap) {
+        assert_eq!(lhs.len(), rhs.len());
+        for value in 0..65536 {
+            assert_eq!(lhs.contains(value), rhs.contains(value));
+        }
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut bitmap = RoaringBitMap::new();
+        assert_eq!(bitmap.len(), 0);
+        assert!(bitmap.is_empty());
 
         assert!(bitmap.insert(94));
+        bitmap.validate().unwrap();
         assert_eq!(bitmap.len(), 1);
         assert!(bitmap.contains(94));
         assert!(!bitmap.insert(94));
+        bitmap.validate().unwrap();
 
         assert!(bitmap.insert(402));
+        bitmap.validate().unwrap();
         assert_eq!(bitmap.len(), 2);
 
         assert!(bitmap.remove(94));
+        bitmap.validate().unwrap();
         assert_eq!(bitmap.len(), 1);
 
         assert!(!bitmap.remove(723));
+        bitmap.validate().unwrap();
 
         assert!(!bitmap.is_empty());
         bitmap.clear();
+        bitmap.validate().unwrap();
+        assert!(bitmap.is_empty());
+    }
+
+    #[test]
+    fn test_union() {
+        let a = from_values((0..3000).chain(100000..100010));
+        let b = from_values(2000..5000);
+        let c = from_values(200000..200005);
+
+        let union = a.union(&b);
+        assert_eq!(union.len(), 5010);
+        assert!(union.contains(0));
+        assert!(union.contains(4999));
+        assert!(union.contains(100009));
+        assert!(!union.contains(5000));
+
+        let mut bitmap = c.clone();
+        bitmap.union_with(&union);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5015);
+        bitmap.union_with(&a);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5015);
+        assert!(bitmap.contains(200004));
+
+        let mut empty = RoaringBitMap::new();
+        empty.union_with(&RoaringBitMap::new());
+        empty.validate().unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_intersection() {
+        // dense and sparse containers sharing key 0, plus unshared keys
+        let a = from_values((0..6000).chain(100000..100010));
+        let b = from_values((2000..2100).chain(200000..200005));
+        let c = from_values((1000..4500).chain(100005..100020));
+
+        assert_eq!(a.intersection(&a).len(), a.len());
+
+        let ab = a.intersection(&b);
+        assert_eq!(ab.len(), 100);
+        assert!(ab.contains(2000));
+        assert!(!ab.contains(200000));
+
+        let ac = a.intersection(&c);
+        assert_eq!(ac.len(), 3505);
+        assert!(ac.contains(1000));
+        assert!(ac.contains(100009));
+        assert!(!ac.contains(100010));
+
+        let mut bitmap = a.clone();
+        bitmap.intersect_with(&c);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 3505);
+        bitmap.intersect_with(&b);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 100);
+
+        let disjoint = from_values(300000..300010);
+        bitmap.intersect_with(&disjoint);
+        bitmap.validate().unwrap();
+        assert!(bitmap.is_empty());
+        assert!(a.intersection(&disjoint).is_empty());
+    }
+
+    #[test]
+    fn test_difference() {
+        let a = from_values((0..6000).chain(100000..100010));
+        let b = from_values((0..2500).chain(100000..100010));
+        let c = from_values(1000..5000);
+
+        // dense minus sparse
+        let ab = a.difference(&b);
+        assert_eq!(ab.len(), 3500);
+        assert!(!ab.contains(0));
+        assert!(ab.contains(2500));
+        assert!(!ab.contains(100000));
+
+        // dense minus dense
+        let ac = a.difference(&c);
+        assert_eq!(ac.len(), 2010);
+        assert!(ac.contains(999));
+        assert!(!ac.contains(1000));
+        assert!(ac.contains(5000));
+
+        // sparse minus dense
+        let ba = b.difference(&a);
+        assert!(ba.is_empty());
+
+        let mut bitmap = b.clone();
+        bitmap.difference_with(&from_values(10..20));
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 2500);
+        bitmap.difference_with(&b);
+        bitmap.validate().unwrap();
+        assert!(bitmap.is_empty());
+    }
+
+    #[test]
+    fn test_symmetric_difference() {
+        let a = from_values((0..6000).chain(100000..100010));
+        let b = from_values((0..2500).chain(100005..100015));
+        let c = from_values(1000..5000);
+
+        let ab = a.symmetric_difference(&b);
+        assert_eq!(ab.len(), 3510);
+        assert!(ab.contains(2500));
+        assert!(ab.contains(100004));
+        assert!(!ab.contains(100005));
+        assert!(ab.contains(100014));
+        assert_eq!(b.symmetric_difference(&a).len(), 3510);
+
+        let ac = a.symmetric_difference(&c);
+        assert_eq!(ac.len(), 2010);
+        assert!(ac.contains(5999));
+        assert!(!ac.contains(4999));
+
+        let mut bitmap = b.clone();
+        bitmap.symmetric_difference_with(&b);
+        bitmap.validate().unwrap();
+        assert!(bitmap.is_empty());
+        bitmap.symmetric_difference_with(&b);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), b.len());
+    }
+
+    #[test]
+    fn test_insert_remove_bookkeeping() {
+        // the promoted and emptied containers are not the first ones
+        let mut bitmap = from_values(vec![1, 2, 3, 300000]);
+        for value in (1 << 16)..(1 << 16) + 5000 {
+            assert!(bitmap.insert(value));
+            bitmap.validate().unwrap();
+        }
+        assert_eq!(bitmap.keys, vec![0, 1, 4]);
+        match *bitmap.containers[1] {
+            Container::Dense(_) => (),
+            _ => panic!("expected a dense container"),
+        }
+        assert_eq!(bitmap.len(), 5004);
+        assert!(bitmap.contains(2));
+        assert!(bitmap.contains(300000));
+
+        for value in (1 << 16)..(1 << 16) + 5000 {
+            assert!(bitmap.remove(value));
+            bitmap.validate().unwrap();
+        }
+        assert_eq!(bitmap.keys, vec![0, 4]);
+        assert!(bitmap.remove(300000));
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.keys, vec![0]);
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_sparse_dense_hysteresis() {
+        let is_dense = |bitmap: &RoaringBitMap| {
+            matches!(*bitmap.containers[0], Container::Dense(_))
+        };
+        let mut bitmap = from_values(0..4095);
+        assert!(!is_dense(&bitmap));
+
+        // alternate around the promotion limit
+        let mut conversions = 0;
+        let mut dense = false;
+        for _ in 0..100 {
+            bitmap.insert(5000);
+            bitmap.validate().unwrap();
+            bitmap.remove(5000);
+            bitmap.validate().unwrap();
+            bitmap.remove(0);
+            bitmap.validate().unwrap();
+            bitmap.insert(0);
+            bitmap.validate().unwrap();
+            if is_dense(&bitmap) != dense {
+                dense = !dense;
+                conversions += 1;
+            }
+        }
+        assert_eq!(conversions, 1);
+        assert!(is_dense(&bitmap));
+
+        // the container is demoted once it is far enough below the limit
+        for value in 0..1023 {
+            bitmap.remove(value);
+            bitmap.validate().unwrap();
+        }
+        assert_eq!(bitmap.len(), 3072);
+        assert!(is_dense(&bitmap));
+        bitmap.remove(1023);
+        bitmap.validate().unwrap();
+        assert!(!is_dense(&bitmap));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut bitmap = from_values(vec![1, 70000]);
+        bitmap.keys.swap(0, 1);
+        assert!(bitmap.validate().is_err());
+
+        let mut bitmap = from_values(vec![1]);
+        bitmap.keys.push(1);
+        assert!(bitmap.validate().is_err());
+        bitmap.containers.push(Box::new(Container::Sparse(Vec::new())));
+        assert!(bitmap.validate().is_err());
+
+        let bitmap = from_runs_at(0, vec![(0, 9), (10, 0)]);
+        assert!(bitmap.validate().is_err());
+        let bitmap = from_runs_at(0, vec![(65535, 1)]);
+        assert!(bitmap.validate().is_err());
+        let bitmap = from_runs_at(0, vec![(0, 9), (11, 0)]);
+        assert!(bitmap.validate().is_ok());
+
+        let mut bitmap = from_values(vec![1, 2]);
+        *bitmap.containers[0] = Container::from_sparse_chunk(&[1, 2]);
+        assert!(bitmap.validate().is_err());
+        *bitmap.containers[0] = Container::Sparse(vec![2, 1]);
+        assert!(bitmap.validate().is_err());
+        let values = (0..5000).collect::<Vec<u16>>();
+        *bitmap.containers[0] = Container::Sparse(values);
+        assert!(bitmap.validate().is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let mut bitmap = from_runs_at(0, vec![(10, 9), (100, 0)]);
+        assert_eq!(bitmap.len(), 11);
+        assert!(bitmap.contains(19));
+        assert!(!bitmap.contains(20));
+        assert!(bitmap.insert(20));
+        bitmap.validate().unwrap();
+        assert!(!bitmap.insert(20));
+        bitmap.validate().unwrap();
+        assert!(bitmap.remove(100));
+        bitmap.validate().unwrap();
+        assert!(!bitmap.remove(100));
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 11);
+        for value in 10..21 {
+            assert!(bitmap.remove(value));
+            bitmap.validate().unwrap();
+        }
+        assert!(bitmap.is_empty());
+
+        // a run container is replaced once it is no longer the smallest kind
+        let mut bitmap = from_runs_at(0, vec![(0, 0), (2, 0)]);
+        bitmap.insert(4);
+        bitmap.validate().unwrap();
+        match *bitmap.containers[0] {
+            Container::Sparse(ref vec) => assert_eq!(*vec, vec![0, 2, 4]),
+            _ => panic!("expected a sparse container"),
+        }
+    }
+
+    #[test]
+    fn test_run_set_operations() {
+        let runs = from_runs_at(0, vec![(0, 99), (1000, 4999), (60000, 5535)]);
+        let equivalent = from_values((0..100).chain(1000..6000).chain(60000..65536));
+        assert_same_chunk(&runs, &equivalent);
+
+        let other_runs = from_runs_at(0, vec![(50, 1999), (62000, 0)]);
+        let sparse = from_values((90..110).chain(5990..6010));
+        let dense = from_values(0..4500);
+
+        for other in &[other_runs, sparse, dense] {
+            assert_same_chunk(&runs.union(other), &equivalent.union(other));
+            assert_same_chunk(&other.union(&runs), &equivalent.union(other));
+            assert_same_chunk(&runs.intersection(other),
+                              &equivalent.intersection(other));
+            assert_same_chunk(&other.intersection(&runs),
+                              &equivalent.intersection(other));
+            assert_same_chunk(&runs.difference(other),
+                              &equivalent.difference(other));
+            assert_same_chunk(&other.difference(&runs),
+                              &other.difference(&equivalent));
+            assert_same_chunk(&runs.symmetric_difference(other),
+                              &equivalent.symmetric_difference(other));
+            assert_same_chunk(&other.symmetric_difference(&runs),
+                              &equivalent.symmetric_difference(other));
+        }
+        assert!(runs.difference(&runs).is_empty());
+    }
+
+    #[test]
+    fn test_run_optimize() {
+        let mut bitmap = from_values((0..5000)
+            .chain(100000..100010)
+            .chain((200000..200100).filter(|v| v % 2 == 0)));
+        assert!(bitmap.run_optimize());
+        bitmap.validate().unwrap();
+        assert!(!bitmap.run_optimize());
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5060);
+        assert!(bitmap.contains(4999));
+        assert!(bitmap.contains(200098));
+        assert!(!bitmap.contains(200099));
+        match *bitmap.containers[0] {
+            Container::Run(ref runs) => assert_eq!(*runs, vec![(0, 4999)]),
+            _ => panic!("expected a run container"),
+        }
+        match *bitmap.containers[1] {
+            Container::Run(ref runs) => assert_eq!(*runs, vec![(34464, 9)]),
+            _ => panic!("expected a run container"),
+        }
+        match *bitmap.containers[2] {
+            Container::Sparse(_) => (),
+            _ => panic!("expected a sparse container"),
+        }
+
+        assert!(bitmap.remove_run_compression());
+        bitmap.validate().unwrap();
+        assert!(!bitmap.remove_run_compression());
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5060);
+        match *bitmap.containers[0] {
+            Container::Dense(_) => (),
+            _ => panic!("expected a dense container"),
+        }
+    }
+
+    #[test]
+    fn test_rank_select() {
+        let (bitmap, values) = mixed_bitmap();
+        for (n, &value) in values.iter().enumerate() {
+            assert_eq!(bitmap.select(n as u64), Some(value));
+            assert_eq!(bitmap.rank(value), n as u64 + 1);
+            if value > 0 && !bitmap.contains(value - 1) {
+                assert_eq!(bitmap.rank(value - 1), n as u64);
+            }
+        }
+        assert_eq!(bitmap.select(values.len() as u64), None);
+        assert_eq!(bitmap.rank(u32::MAX), values.len() as u64);
+        assert_eq!(bitmap.rank(65535), 5000);
+        assert_eq!(bitmap.rank(200000), 5003);
+
+        assert_eq!(RoaringBitMap::new().rank(7), 0);
+        assert_eq!(RoaringBitMap::new().select(0), None);
+    }
+
+    #[test]
+    fn test_min_max() {
+        let (mut bitmap, mut values) = mixed_bitmap();
+        assert_eq!(bitmap.min(), Some(0));
+        assert_eq!(bitmap.max(), Some((6 << 16) - 1));
+
+        for _ in 0..10 {
+            assert_eq!(bitmap.pop_max(), values.pop());
+            bitmap.validate().unwrap();
+            assert_eq!(bitmap.pop_min(), Some(values.remove(0)));
+            bitmap.validate().unwrap();
+            assert_eq!(bitmap.len(), values.len());
+        }
+        assert_eq!(bitmap.min(), Some(20));
+        assert_eq!(bitmap.max(), Some((5 << 16) + 5));
+
+        let mut popped = Vec::new();
+        while let Some(value) = bitmap.pop_max() {
+            bitmap.validate().unwrap();
+            popped.push(value);
+        }
+        popped.reverse();
+        assert_eq!(popped, values);
+        assert!(bitmap.is_empty());
+        assert_eq!(bitmap.min(), None);
+        assert_eq!(bitmap.max(), None);
+    }
+
+    #[test]
+    fn test_insert_range() {
+        let (mut bitmap, values) = mixed_bitmap();
+        let len = values.len() as u64;
+
+        assert_eq!(bitmap.insert_range(4000..4000), 0);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.insert_range(4000..10000), 3000);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.insert_range(65530..=70005), 4476 - 2);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.insert_range((5 << 16) + 5..(5 << 16) + 20), 10);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.insert_range(1 << 17..1 << 17), 0);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len() as u64, len + 3000 + 4474 + 10);
+        assert!(bitmap.contains(9999));
+        assert!(!bitmap.contains(10000));
+        assert!(bitmap.contains(65530));
+        assert!(bitmap.contains(70005));
+        assert!(!bitmap.contains(70006));
+        assert!(bitmap.contains((5 << 16) + 19));
+
+        // only the even values below 4000 are present
+        let len = bitmap.len() as u64;
+        assert_eq!(bitmap.insert_range(4000..=u32::MAX), (1 << 32) - 4000 - (len - 2000));
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len() as u64, (1 << 32) - 2000);
+        assert_eq!(bitmap.insert_range(..), 2000);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.keys.len(), 1 << 16);
+        assert!(bitmap.contains(u32::MAX));
+
+        let mut bitmap = RoaringBitMap::new();
+        assert_eq!(bitmap.insert_range(..), 1 << 32);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.max(), Some(u32::MAX));
+        assert_eq!(bitmap.insert_range(5..5), 0);
+        bitmap.validate().unwrap();
+    }
+
+    #[test]
+    fn test_remove_range() {
+        let (mut bitmap, _) = mixed_bitmap();
+
+        assert_eq!(bitmap.remove_range(10..10), 0);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.remove_range(10..1010), 500);
+        bitmap.validate().unwrap();
+        assert!(bitmap.contains(8));
+        assert!(!bitmap.contains(10));
+        assert!(bitmap.contains(1010));
+        assert_eq!(bitmap.remove_range(4000..=131071), 3003);
+        bitmap.validate().unwrap();
+        assert!(!bitmap.contains(70000));
+        assert_eq!(bitmap.keys, vec![0, 5]);
+        assert_eq!(bitmap.remove_range((5 << 16) + 1..), 15);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 1500 + 1);
+        assert_eq!(bitmap.remove_range(..), 1501);
+        bitmap.validate().unwrap();
         assert!(bitmap.is_empty());
+
+        let mut bitmap = RoaringBitMap::new();
+        bitmap.insert_range(..);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.remove_range(1..u32::MAX), (1 << 32) - 2);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![0, u32::MAX]);
+    }
+
+    #[test]
+    fn test_flip() {
+        let (mut bitmap, values) = mixed_bitmap();
+
+        let flipped = bitmap.flip(1000..=(5 << 16) + 4);
+        let in_range = |v: u32| (1000..=(5 << 16) + 4).contains(&v);
+        for value in (0..(6 << 16)).step_by(7).chain(values.iter().cloned()) {
+            let expected = bitmap.contains(value) != in_range(value);
+            assert_eq!(flipped.contains(value), expected);
+        }
+        let inside = values.iter().filter(|&&v| in_range(v)).count() as u64;
+        let flipped_len = bitmap.len() as u64 + ((5 << 16) + 5 - 1000) - 2 * inside;
+        assert_eq!(flipped.len() as u64, flipped_len);
+        assert_eq!(flipped.keys, vec![0, 1, 2, 3, 4, 5]);
+
+        // flipping twice restores the bitmap
+        let restored = flipped.flip(1000..=(5 << 16) + 4);
+        assert_eq!(restored.iter().collect::<Vec<u32>>(),
+                   bitmap.iter().collect::<Vec<u32>>());
+
+        // containers emptied by the flip are dropped
+        bitmap.flip_inplace(5 << 16..(5 << 16) + 10);
+        bitmap.validate().unwrap();
+        bitmap.flip_inplace((5 << 16) + 65530..6 << 16);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.keys, vec![0, 1]);
+        bitmap.flip_inplace(..);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len() as u64, (1 << 32) - 5003);
+        bitmap.flip_inplace(..);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5003);
+        bitmap.flip_inplace(10..10);
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5003);
+    }
+
+    #[test]
+    fn test_range_queries() {
+        let (mut bitmap, _) = mixed_bitmap();
+        bitmap.insert_range(6 << 16..8 << 16);
+        bitmap.validate().unwrap();
+
+        let values = bitmap.iter().collect::<Vec<u32>>();
+        let ranges = vec![(0, 0), (0, 4999), (1, 5000), (4999, 70000),
+                          (70001, 199999), (5000, (5 << 16) + 3),
+                          ((5 << 16) + 9, (7 << 16) + 5), (6 << 16, (8 << 16) - 1),
+                          (0, u32::MAX)];
+        for (start, end) in ranges {
+            let count = values.iter().filter(|&&v| v >= start && v <= end).count();
+            assert_eq!(bitmap.range_cardinality(start..=end), count as u64);
+            assert_eq!(bitmap.contains_range(start..=end),
+                       count as u64 == end as u64 - start as u64 + 1);
+            assert_eq!(bitmap.intersects_range(start..=end), count > 0);
+        }
+
+        assert_eq!(bitmap.range_cardinality(10..10), 0);
+        assert!(bitmap.contains_range(10..10));
+        assert!(!bitmap.intersects_range(10..10));
+        assert!(bitmap.contains_range(5 << 16..(5 << 16) + 10));
+        assert!(!bitmap.contains_range(5 << 16..=(5 << 16) + 10));
+        assert!(bitmap.contains_range((5 << 16) + 9..=(5 << 16) + 9));
+        assert!(bitmap.contains_range((6 << 16) - 6..(6 << 16) + 10));
+        assert!(!bitmap.contains_range((6 << 16) - 7..(6 << 16) + 10));
+        assert!(!RoaringBitMap::new().intersects_range(..));
+    }
+
+    #[test]
+    fn test_subset_and_disjoint() {
+        let bitmaps = mixed_bitmaps();
+        let (dense, sparse, runs, empty) = (&bitmaps[0], &bitmaps[1], &bitmaps[2],
+                                            &bitmaps[5]);
+        for lhs in &bitmaps {
+            assert!(lhs.is_subset(lhs));
+            assert!(empty.is_subset(lhs));
+            for rhs in &bitmaps {
+                let intersection = lhs.intersection(rhs);
+                let expected = intersection.len() == lhs.len();
+                assert_eq!(lhs.is_subset(rhs), expected);
+                assert_eq!(rhs.is_superset(lhs), expected);
+                assert_eq!(lhs.intersects(rhs), !intersection.is_empty());
+                assert_eq!(lhs.is_disjoint(rhs), intersection.is_empty());
+            }
+        }
+        assert!(!sparse.is_subset(dense));
+        assert!(sparse.intersects(dense));
+        assert!(!runs.is_subset(dense));
+        assert!(runs.intersects(dense));
+    }
+
+    #[test]
+    fn test_set_operation_lengths() {
+        let bitmaps = mixed_bitmaps();
+        let (dense, sparse, runs, empty) = (&bitmaps[0], &bitmaps[1], &bitmaps[2],
+                                            &bitmaps[5]);
+        for lhs in &bitmaps {
+            for rhs in &bitmaps {
+                assert_eq!(lhs.intersection_len(rhs),
+                           lhs.intersection(rhs).len() as u64);
+                assert_eq!(lhs.union_len(rhs), lhs.union(rhs).len() as u64);
+                assert_eq!(lhs.difference_len(rhs),
+                           lhs.difference(rhs).len() as u64);
+                assert_eq!(lhs.symmetric_difference_len(rhs),
+                           lhs.symmetric_difference(rhs).len() as u64);
+            }
+        }
+        assert_eq!(dense.jaccard_index(dense), 1.0);
+        assert_eq!(dense.jaccard_index(empty), 0.0);
+        assert_eq!(empty.jaccard_index(empty), 1.0);
+        assert_eq!(runs.jaccard_index(sparse), 4.0 / 109.0);
     }
 }
@@ -0,0 +1,115 @@
+//! Operator overloads for the set operations of `RoaringBitMap` and
+//! `RoaringTreemap`.
+//!
+//! Whenever the left operand is owned, its containers are reused for the
+//! result instead of allocating a new bitmap.
+
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign,
+               Sub, SubAssign};
+
+use {RoaringBitMap, RoaringTreemap};
+
+macro_rules! impl_binop {
+    ($T:ident, $Op:ident, $op:ident, $OpAssign:ident, $op_assign:ident,
+     $method:ident, $method_with:ident) => {
+        impl $Op<$T> for $T {
+            type Output = $T;
+
+            fn $op(mut self, rhs: $T) -> $T {
+                self.$method_with(&rhs);
+                self
+            }
+        }
+
+        impl<'a> $Op<&'a $T> for $T {
+            type Output = $T;
+
+            fn $op(mut self, rhs: &'a $T) -> $T {
+                self.$method_with(rhs);
+                self
+            }
+        }
+
+        impl<'a> $Op<$T> for &'a $T {
+            type Output = $T;
+
+            fn $op(self, rhs: $T) -> $T {
+                self.$method(&rhs)
+            }
+        }
+
+        impl<'a, 'b> $Op<&'b $T> for &'a $T {
+            type Output = $T;
+
+            fn $op(self, rhs: &'b $T) -> $T {
+                self.$method(rhs)
+            }
+        }
+
+        impl $OpAssign<$T> for $T {
+            fn $op_assign(&mut self, rhs: $T) {
+                self.$method_with(&rhs);
+            }
+        }
+
+        impl<'a> $OpAssign<&'a $T> for $T {
+            fn $op_assign(&mut self, rhs: &'a $T) {
+                self.$method_with(rhs);
+            }
+        }
+    }
+}
+
+impl_binop!(RoaringBitMap, BitOr, bitor, BitOrAssign, bitor_assign,
+            union, union_with);
+impl_binop!(RoaringBitMap, BitAnd, bitand, BitAndAssign, bitand_assign,
+            intersection, intersect_with);
+impl_binop!(RoaringBitMap, Sub, sub, SubAssign, sub_assign,
+            difference, difference_with);
+impl_binop!(RoaringBitMap, BitXor, bitxor, BitXorAssign, bitxor_assign,
+            symmetric_difference, symmetric_difference_with);
+
+impl_binop!(RoaringTreemap, BitOr, bitor, BitOrAssign, bitor_assign,
+            union, union_with);
+impl_binop!(RoaringTreemap, BitAnd, bitand, BitAndAssign, bitand_assign,
+            intersection, intersect_with);
+impl_binop!(RoaringTreemap, Sub, sub, SubAssign, sub_assign,
+            difference, difference_with);
+impl_binop!(RoaringTreemap, BitXor, bitxor, BitXorAssign, bitxor_assign,
+            symmetric_difference, symmetric_difference_with);
+
+#[cfg(test)]
+mod tests {
+    use from_values;
+
+    #[test]
+    fn test_operators() {
+        let a = from_values(0..10);
+        let b = from_values(5..15);
+        let c = from_values(100000..100005);
+
+        assert_eq!((&a | &b).len(), 15);
+        assert_eq!((&a & &b).len(), 5);
+        assert_eq!((&a - &b).len(), 5);
+        assert_eq!((&a ^ &b).len(), 10);
+
+        assert_eq!((&a & &b | c.clone()).len(), 10);
+        assert_eq!((a.clone() | &c).len(), 15);
+        assert_eq!((a.clone() - b.clone()).len(), 5);
+        assert_eq!((&a ^ b.clone()).len(), 10);
+
+        let mut bitmap = a.clone();
+        bitmap |= &c;
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 15);
+        bitmap &= b.clone();
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5);
+        bitmap ^= &a;
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.len(), 5);
+        bitmap -= a;
+        bitmap.validate().unwrap();
+        assert!(bitmap.is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! Helpers for run containers.
+//!
+//! A run container stores sorted, non-overlapping and non-adjacent
+//! `(start, length)` pairs, where `length` is the number of values following
+//! `start` in the run, so that a run can cover all 2**16 values of a chunk.
+//!
+//! * Daniel Lemire, Gregory Ssi-Yan-Kai, Owen Kaser, [Consistently faster and
+//!   smaller compressed bitmaps with Roaring](http://arxiv.org/abs/1603.06549)
+
+use bitset::BitSet;
+use std::cmp;
+
+/// Returns the index of the run containing `value`, or the index at which a
+/// run starting at `value` would be inserted.
+fn search(runs: &[(u16, u16)], value: u16) -> Result<usize, usize> {
+    match runs.binary_search_by_key(&value, |&(start, _)| start) {
+        Ok(i) => Ok(i),
+        Err(0) => Err(0),
+        Err(i) => {
+            let (start, length) = runs[i - 1];
+            if value as u32 <= start as u32 + length as u32 {
+                Ok(i - 1)
+            } else {
+                Err(i)
+            }
+        }
+    }
+}
+
+pub fn len(runs: &[(u16, u16)]) -> usize {
+    runs.iter().map(|&(_, length)| length as usize + 1).sum()
+}
+
+/// Size in bytes of a run container holding `run_count` runs in the portable
+/// serialization format.
+pub fn size_in_bytes(run_count: usize) -> usize {
+    2 + 4 * run_count
+}
+
+pub fn contains(runs: &[(u16, u16)], value: u16) -> bool {
+    search(runs, value).is_ok()
+}
+
+/// Returns the number of values lower than or equal to `value`.
+pub fn rank(runs: &[(u16, u16)], value: u16) -> usize {
+    let mut rank = 0;
+    for &(start, length) in runs {
+        if start > value {
+            break;
+        }
+        rank += cmp::min(length, value - start) as usize + 1;
+    }
+    rank
+}
+
+/// Returns the `n`-th smallest value, counting from zero.
+pub fn select(runs: &[(u16, u16)], n: usize) -> Option<u16> {
+    let mut n = n;
+    for &(start, length) in runs {
+        if n <= length as usize {
+            return Some(start + n as u16);
+        }
+        n -= length as usize + 1;
+    }
+    None
+}
+
+pub fn insert(runs: &mut Vec<(u16, u16)>, value: u16) -> bool {
+    let i = match search(runs, value) {
+        Ok(_) => return false,
+        Err(i) => i,
+    };
+    let extends_prev = i > 0 && {
+        let (start, length) = runs[i - 1];
+        start as u32 + length as u32 + 1 == value as u32
+    };
+    let extends_next = i < runs.len() && runs[i].0 as u32 == value as u32 + 1;
+    match (extends_prev, extends_next) {
+        (true, true) => {
+            let (_, length) = runs.remove(i);
+            runs[i - 1].1 += length + 2;
+        }
+        (true, false) => runs[i - 1].1 += 1,
+        (false, true) => runs[i] = (value, runs[i].1 + 1),
+        (false, false) => runs.insert(i, (value, 0)),
+    }
+    true
+}
+
+pub fn remove(runs: &mut Vec<(u16, u16)>, value: u16) -> bool {
+    let i = match search(runs, value) {
+        Ok(i) => i,
+        Err(_) => return false,
+    };
+    let (start, length) = runs[i];
+    let end = start + length;
+    if length == 0 {
+        runs.remove(i);
+    } else if value == start {
+        runs[i] = (start + 1, length - 1);
+    } else if value == end {
+        runs[i].1 -= 1;
+    } else {
+        runs[i].1 = value - start - 1;
+        runs.insert(i + 1, (value + 1, end - value - 1));
+    }
+    true
+}
+
+/// Returns the values covered by `runs` in ascending order.
+pub fn values<'a>(runs: &'a [(u16, u16)]) -> impl Iterator<Item = u16> + 'a {
+    runs.iter().flat_map(|&(start, length)| {
+        (start as u32..start as u32 + length as u32 + 1).map(|val| val as u16)
+    })
+}
+
+pub fn to_bitset(runs: &[(u16, u16)]) -> BitSet {
+    values(runs).collect()
+}
+
+/// Builds runs out of values given in strictly ascending order.
+pub fn from_sorted<I: IntoIterator<Item = u16>>(values: I) -> Vec<(u16, u16)> {
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for val in values {
+        match runs.last_mut() {
+            Some(&mut (start, ref mut length))
+                if start as u32 + *length as u32 + 1 == val as u32 => {
+                *length += 1;
+            }
+            _ => runs.push((val, 0)),
+        }
+    }
+    runs
+}
+
+/// Counts the runs formed by values given in strictly ascending order.
+pub fn count<I: IntoIterator<Item = u16>>(values: I) -> usize {
+    let mut count = 0;
+    let mut next: Option<u32> = None;
+    for val in values {
+        if next != Some(val as u32) {
+            count += 1;
+        }
+        next = Some(val as u32 + 1);
+    }
+    count
+}
+
+/// Appends the half-open interval `[lo, hi)` to `runs`, merging it into the
+/// last run when they are adjacent.
+fn push_interval(runs: &mut Vec<(u16, u16)>, lo: u32, hi: u32) {
+    if let Some(&mut (start, ref mut length)) = runs.last_mut() {
+        if start as u32 + *length as u32 + 1 == lo {
+            *length += (hi - lo) as u16;
+            return;
+        }
+    }
+    runs.push((lo as u16, (hi - lo - 1) as u16));
+}
+
+/// Combines two run containers, keeping the values for which `keep` returns
+/// true given whether they are present in `lhs` and in `rhs`.
+pub fn combine(lhs: &[(u16, u16)], rhs: &[(u16, u16)],
+               keep: fn(bool, bool) -> bool) -> Vec<(u16, u16)> {
+    let intervals = |runs: &[(u16, u16)]| {
+        runs.iter()
+            .map(|&(start, length)| (start as u32, start as u32 + length as u32 + 1))
+            .collect::<Vec<(u32, u32)>>()
+    };
+    combine_intervals(intervals(lhs), intervals(rhs), keep)
+}
+
+/// Combines two sequences of sorted, non-overlapping half-open intervals
+/// `[lo, hi)` into runs, keeping the values for which `keep` returns true
+/// given whether they are present in `lhs` and in `rhs`.
+pub fn combine_intervals<L, R>(lhs: L, rhs: R, keep: fn(bool, bool) -> bool)
+                               -> Vec<(u16, u16)>
+    where L: IntoIterator<Item = (u32, u32)>,
+          R: IntoIterator<Item = (u32, u32)>
+{
+    let mut lhs = lhs.into_iter().peekable();
+    let mut rhs = rhs.into_iter().peekable();
+    let mut result = Vec::new();
+    let mut pos = 0;
+    loop {
+        while lhs.peek().is_some_and(|&(_, hi)| hi <= pos) {
+            lhs.next();
+        }
+        while rhs.peek().is_some_and(|&(_, hi)| hi <= pos) {
+            rhs.next();
+        }
+        // the values from `pos` up to the next interval bound are either all
+        // present or all missing on each side
+        let (in_lhs, lhs_bound) = match lhs.peek() {
+            Some(&(lo, hi)) => (lo <= pos, if lo <= pos { hi } else { lo }),
+            None => (false, u32::MAX),
+        };
+        let (in_rhs, rhs_bound) = match rhs.peek() {
+            Some(&(lo, hi)) => (lo <= pos, if lo <= pos { hi } else { lo }),
+            None => (false, u32::MAX),
+        };
+        if lhs_bound == u32::MAX && rhs_bound == u32::MAX {
+            break;
+        }
+        let bound = cmp::min(lhs_bound, rhs_bound);
+        if keep(in_lhs, in_rhs) {
+            push_interval(&mut result, pos, bound);
+        }
+        pos = bound;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{combine, combine_intervals, contains, count, from_sorted, insert,
+                len, rank, remove, select, values};
+
+    #[test]
+    fn test_insert_remove() {
+        let mut runs = Vec::new();
+        assert!(insert(&mut runs, 5));
+        assert!(insert(&mut runs, 7));
+        assert!(!insert(&mut runs, 7));
+        assert_eq!(runs, vec![(5, 0), (7, 0)]);
+        assert!(insert(&mut runs, 6));
+        assert_eq!(runs, vec![(5, 2)]);
+        assert!(insert(&mut runs, 4));
+        assert!(insert(&mut runs, 8));
+        assert_eq!(runs, vec![(4, 4)]);
+        assert!(contains(&runs, 8));
+        assert!(!contains(&runs, 9));
+
+        assert!(remove(&mut runs, 6));
+        assert_eq!(runs, vec![(4, 1), (7, 1)]);
+        assert!(!remove(&mut runs, 6));
+        assert!(remove(&mut runs, 4));
+        assert!(remove(&mut runs, 8));
+        assert_eq!(runs, vec![(5, 0), (7, 0)]);
+        assert!(remove(&mut runs, 5));
+        assert_eq!(len(&runs), 1);
+
+        let mut full = vec![(0, 65535)];
+        assert_eq!(len(&full), 65536);
+        assert!(!insert(&mut full, 65535));
+        assert!(remove(&mut full, 65535));
+        assert!(insert(&mut full, 65535));
+        assert_eq!(full, vec![(0, 65535)]);
+    }
+
+    #[test]
+    fn test_rank_select() {
+        let runs = vec![(3, 2), (10, 0), (65530, 5)];
+        assert_eq!(rank(&runs, 0), 0);
+        assert_eq!(rank(&runs, 3), 1);
+        assert_eq!(rank(&runs, 9), 3);
+        assert_eq!(rank(&runs, 10), 4);
+        assert_eq!(rank(&runs, 65535), 10);
+        assert_eq!(select(&runs, 0), Some(3));
+        assert_eq!(select(&runs, 3), Some(10));
+        assert_eq!(select(&runs, 9), Some(65535));
+        assert_eq!(select(&runs, 10), None);
+    }
+
+    #[test]
+    fn test_combine() {
+        let lhs = from_sorted((0..10).chain(20..30));
+        let rhs = from_sorted((5..25).chain(65530..=65535));
+        assert_eq!(lhs, vec![(0, 9), (20, 9)]);
+        assert_eq!(count((0..10).chain(20..30)), 2);
+
+        assert_eq!(combine(&lhs, &rhs, |a, b| a || b),
+                   vec![(0, 29), (65530, 5)]);
+        assert_eq!(combine(&lhs, &rhs, |a, b| a && b), vec![(5, 4), (20, 4)]);
+        assert_eq!(combine(&lhs, &rhs, |a, b| a && !b), vec![(0, 4), (25, 4)]);
+        assert_eq!(combine(&lhs, &rhs, |a, b| a != b),
+                   vec![(0, 4), (10, 9), (25, 4), (65530, 5)]);
+        assert_eq!(values(&rhs).count(), 26);
+
+        let lhs = vec![(0, 3), (5, 6), (10, 1 << 16)];
+        let rhs = vec![(2, 8), (65535, 1 << 16)];
+        assert_eq!(combine_intervals(lhs.clone(), rhs.clone(), |a, b| a || b),
+                   vec![(0, 7), (10, 65525)]);
+        assert_eq!(combine_intervals(lhs.clone(), rhs.clone(), |a, b| a && b),
+                   vec![(2, 0), (5, 0), (65535, 0)]);
+        assert_eq!(combine_intervals(lhs, rhs, |a, b| a && !b),
+                   vec![(0, 1), (10, 65524)]);
+        assert!(combine_intervals(vec![], vec![], |a, b| a || b).is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+//! `Serialize` and `Deserialize` implementations, enabled by the `serde`
+//! feature.
+//!
+//! Binary formats receive the portable serialization format as a byte array,
+//! while human-readable formats receive a sorted sequence in which runs of
+//! consecutive values are written as inclusive `[start, end]` ranges, e.g.
+//! `[1, 3, [10, 20]]`.
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, SerializeTuple, Serializer};
+use std::fmt;
+
+use RoaringBitMap;
+
+/// A single value or an inclusive range of values.
+enum Entry {
+    Value(u32),
+    Range(u32, u32),
+}
+
+impl Serialize for Entry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Entry::Value(value) => serializer.serialize_u32(value),
+            Entry::Range(start, end) => {
+                let mut tuple = serializer.serialize_tuple(2)?;
+                tuple.serialize_element(&start)?;
+                tuple.serialize_element(&end)?;
+                tuple.end()
+            }
+        }
+    }
+}
+
+struct EntryVisitor;
+
+impl<'de> Visitor<'de> for EntryVisitor {
+    type Value = Entry;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a u32 or a [start, end] pair of u32")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Entry, E> {
+        if value > u32::MAX as u64 {
+            return Err(E::invalid_value(de::Unexpected::Unsigned(value), &self));
+        }
+        Ok(Entry::Value(value as u32))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Entry, E> {
+        if value < 0 || value > u32::MAX as i64 {
+            return Err(E::invalid_value(de::Unexpected::Signed(value), &self));
+        }
+        Ok(Entry::Value(value as u32))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Entry, A::Error> {
+        let start: u32 = seq.next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let end: u32 = seq.next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        if seq.next_element::<u32>()?.is_some() {
+            return Err(de::Error::invalid_length(3, &self));
+        }
+        if start > end {
+            return Err(de::Error::custom("range start is greater than its end"));
+        }
+        Ok(Entry::Range(start, end))
+    }
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Entry, D::Error> {
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+/// Returns the values of `bitmap` grouped into maximal runs, reading the
+/// runs of each container and joining those adjacent across containers.
+fn entries(bitmap: &RoaringBitMap) -> Vec<Entry> {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for (&key, container) in bitmap.keys.iter().zip(bitmap.containers.iter()) {
+        let base = (key as u32) << 16;
+        for &(start, length) in container.to_runs().iter() {
+            let (start, end) = (base | start as u32, base | (start + length) as u32);
+            match ranges.last_mut() {
+                Some(&mut (_, ref mut last)) if *last + 1 == start => *last = end,
+                _ => ranges.push((start, end)),
+            }
+        }
+    }
+    ranges.into_iter()
+        .map(|(start, end)| if start == end {
+            Entry::Value(start)
+        } else {
+            Entry::Range(start, end)
+        })
+        .collect()
+}
+
+impl Serialize for RoaringBitMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let entries = entries(self);
+            let mut seq = serializer.serialize_seq(Some(entries.len()))?;
+            for entry in &entries {
+                seq.serialize_element(entry)?;
+            }
+            seq.end()
+        } else {
+            let mut bytes = Vec::with_capacity(self.serialized_size());
+            self.serialize_into(&mut bytes).map_err(::serde::ser::Error::custom)?;
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+}
+
+struct EntriesVisitor;
+
+impl<'de> Visitor<'de> for EntriesVisitor {
+    type Value = RoaringBitMap;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of values and [start, end] ranges")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A)
+                                    -> Result<RoaringBitMap, A::Error> {
+        let mut bitmap = RoaringBitMap::new();
+        while let Some(entry) = seq.next_element()? {
+            match entry {
+                Entry::Value(value) => {
+                    bitmap.insert(value);
+                }
+                Entry::Range(start, end) => {
+                    bitmap.insert_range(start..=end);
+                }
+            }
+        }
+        Ok(bitmap)
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = RoaringBitMap;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a bitmap in the portable serialization format")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<RoaringBitMap, E> {
+        RoaringBitMap::deserialize_from(bytes).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A)
+                                    -> Result<RoaringBitMap, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for RoaringBitMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+                                         -> Result<RoaringBitMap, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_seq(EntriesVisitor)
+        } else {
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate bincode;
+    extern crate serde_json;
+
+    use RoaringBitMap;
+
+    fn bitmap() -> RoaringBitMap {
+        let mut bitmap = RoaringBitMap::new();
+        bitmap.insert(1);
+        bitmap.validate().unwrap();
+        bitmap.insert(3);
+        bitmap.validate().unwrap();
+        bitmap.insert_range(10..=20);
+        bitmap.validate().unwrap();
+        bitmap.insert_range(65530..70000);
+        bitmap.validate().unwrap();
+        bitmap.insert(u32::MAX);
+        bitmap.validate().unwrap();
+        bitmap
+    }
+
+    #[test]
+    fn test_json() {
+        let bitmap = bitmap();
+        let json = serde_json::to_string(&bitmap).unwrap();
+        assert_eq!(json, "[1,3,[10,20],[65530,69999],4294967295]");
+        let copy: RoaringBitMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.iter().collect::<Vec<u32>>(),
+                   bitmap.iter().collect::<Vec<u32>>());
+
+        let empty: RoaringBitMap = serde_json::from_str("[]").unwrap();
+        assert!(empty.is_empty());
+        let overlapping: RoaringBitMap = serde_json::from_str("[[1,3],2,[3,4]]").unwrap();
+        assert_eq!(overlapping.iter().collect::<Vec<u32>>(), vec![1, 2, 3, 4]);
+
+        assert!(serde_json::from_str::<RoaringBitMap>("[-1]").is_err());
+        assert!(serde_json::from_str::<RoaringBitMap>("[4294967296]").is_err());
+        assert!(serde_json::from_str::<RoaringBitMap>("[[3,1]]").is_err());
+        assert!(serde_json::from_str::<RoaringBitMap>("[[1,2,3]]").is_err());
+        assert!(serde_json::from_str::<RoaringBitMap>("[[1]]").is_err());
+
+        let mut full = RoaringBitMap::new();
+        full.insert_range(..);
+        full.validate().unwrap();
+        assert_eq!(serde_json::to_string(&full).unwrap(), "[[0,4294967295]]");
+    }
+
+    #[test]
+    fn test_bincode() {
+        let mut bitmap = bitmap();
+        bitmap.run_optimize();
+        bitmap.validate().unwrap();
+        let bytes = bincode::serialize(&bitmap).unwrap();
+        let mut portable = Vec::new();
+        bitmap.serialize_into(&mut portable).unwrap();
+        // bincode prefixes the byte array with its length
+        assert_eq!(&bytes[8..], &portable[..]);
+
+        let copy: RoaringBitMap = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(copy.iter().collect::<Vec<u32>>(),
+                   bitmap.iter().collect::<Vec<u32>>());
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(bincode::deserialize::<RoaringBitMap>(truncated).is_err());
+    }
+}
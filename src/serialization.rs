@@ -0,0 +1,400 @@
+//! Serialization of `RoaringBitMap` in the portable format shared with the
+//! Java and C implementations.
+//!
+//! * [Roaring Bitmap format specification]
+//!   (https://github.com/RoaringBitmap/RoaringFormatSpec)
+
+use bitset::{BitSet, WORD_COUNT};
+use std::io::{self, Read, Write};
+
+use {Config, Container, RoaringBitMap};
+
+pub const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+pub const SERIAL_COOKIE: u16 = 12347;
+// below this many containers, the offset header is omitted when the bitmap
+// holds run containers
+pub const NO_OFFSET_THRESHOLD: usize = 4;
+// array containers hold at most this many values, bitmap containers more
+pub const ARRAY_CONTAINER_MAX_LEN: usize = 4096;
+pub const BITMAP_CONTAINER_SIZE_IN_BYTES: usize = 8192;
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+impl Container {
+    fn serialized_size(&self) -> usize {
+        match *self {
+            Container::Run(ref runs) => ::run::size_in_bytes(runs.len()),
+            _ => {
+                let len = self.len();
+                if len <= ARRAY_CONTAINER_MAX_LEN {
+                    2 * len
+                } else {
+                    BITMAP_CONTAINER_SIZE_IN_BYTES
+                }
+            }
+        }
+    }
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match *self {
+            Container::Run(ref runs) => {
+                writer.write_all(&(runs.len() as u16).to_le_bytes())?;
+                for &(start, length) in runs {
+                    writer.write_all(&start.to_le_bytes())?;
+                    writer.write_all(&length.to_le_bytes())?;
+                }
+            }
+            Container::Sparse(ref vec) if self.len() <= ARRAY_CONTAINER_MAX_LEN => {
+                for &val in vec {
+                    writer.write_all(&val.to_le_bytes())?;
+                }
+            }
+            Container::Dense(ref bitset) if self.len() <= ARRAY_CONTAINER_MAX_LEN => {
+                for val in bitset.iter() {
+                    writer.write_all(&val.to_le_bytes())?;
+                }
+            }
+            _ => {
+                for &word in self.to_bitset().words().iter() {
+                    writer.write_all(&word.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn deserialize_from<R: Read>(reader: &mut R, len: usize, is_run: bool)
+                                 -> io::Result<Container> {
+        let mut container = if is_run {
+            let run_count = read_u16(reader)?;
+            let mut runs: Vec<(u16, u16)> = Vec::with_capacity(run_count as usize);
+            for _ in 0..run_count {
+                let start = read_u16(reader)?;
+                let length = read_u16(reader)?;
+                if start as u32 + length as u32 > u16::MAX as u32 {
+                    return Err(invalid_data("run exceeds the container"));
+                }
+                if let Some(&mut (prev_start, ref mut prev_length)) = runs.last_mut() {
+                    let prev_end = prev_start as u32 + *prev_length as u32;
+                    if prev_end >= start as u32 {
+                        return Err(invalid_data("runs are not sorted"));
+                    } else if prev_end + 1 == start as u32 {
+                        *prev_length += length + 1;
+                        continue;
+                    }
+                }
+                runs.push((start, length));
+            }
+            Container::Run(runs)
+        } else if len <= ARRAY_CONTAINER_MAX_LEN {
+            let mut vec: Vec<u16> = Vec::with_capacity(len);
+            for _ in 0..len {
+                let val = read_u16(reader)?;
+                if vec.last().is_some_and(|&last| last >= val) {
+                    return Err(invalid_data("array container is not sorted"));
+                }
+                vec.push(val);
+            }
+            Container::Sparse(vec)
+        } else {
+            let mut words = Box::new([0; WORD_COUNT]);
+            for word in words.iter_mut() {
+                *word = read_u64(reader)?;
+            }
+            Container::Dense(BitSet::from_words(words))
+        };
+        if container.len() != len {
+            return Err(invalid_data("container cardinality mismatch"));
+        }
+        container.normalize(&Config::default());
+        Ok(container)
+    }
+}
+
+impl RoaringBitMap {
+    fn has_run_containers(&self) -> bool {
+        self.containers.iter().any(|c| matches!(**c, Container::Run(_)))
+    }
+
+    /// Returns the size of the header preceding the containers.
+    fn serialized_header_size(&self) -> usize {
+        let count = self.containers.len();
+        if self.has_run_containers() {
+            let offsets = if count >= NO_OFFSET_THRESHOLD { 4 * count } else { 0 };
+            4 + count.div_ceil(8) + 4 * count + offsets
+        } else {
+            8 + 8 * count
+        }
+    }
+
+    /// Returns the number of bytes written by `serialize_into`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(0..10);
+    ///
+    /// let mut bytes = Vec::new();
+    /// bitmap.serialize_into(&mut bytes).unwrap();
+    /// assert_eq!(bytes.len(), bitmap.serialized_size());
+    /// ```
+    pub fn serialized_size(&self) -> usize {
+        self.serialized_header_size() +
+            self.containers.iter().map(|c| c.serialized_size()).sum::<usize>()
+    }
+
+    /// Writes the bitmap in the portable Roaring format, understood by the
+    /// Java and C implementations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(70000);
+    ///
+    /// let mut bytes = Vec::new();
+    /// bitmap.serialize_into(&mut bytes).unwrap();
+    /// let copy = RoaringBitMap::deserialize_from(&bytes[..]).unwrap();
+    /// assert!(copy.contains(70000));
+    /// ```
+    pub fn serialize_into<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let count = self.containers.len();
+        let has_runs = self.has_run_containers();
+        if has_runs {
+            let cookie = SERIAL_COOKIE as u32 | ((count as u32 - 1) << 16);
+            writer.write_all(&cookie.to_le_bytes())?;
+            let mut run_bitset = vec![0u8; count.div_ceil(8)];
+            for (i, container) in self.containers.iter().enumerate() {
+                if let Container::Run(_) = **container {
+                    run_bitset[i / 8] |= 1 << (i % 8);
+                }
+            }
+            writer.write_all(&run_bitset)?;
+        } else {
+            writer.write_all(&SERIAL_COOKIE_NO_RUNCONTAINER.to_le_bytes())?;
+            writer.write_all(&(count as u32).to_le_bytes())?;
+        }
+
+        for (&key, container) in self.keys.iter().zip(self.containers.iter()) {
+            writer.write_all(&key.to_le_bytes())?;
+            writer.write_all(&((container.len() - 1) as u16).to_le_bytes())?;
+        }
+
+        if !has_runs || count >= NO_OFFSET_THRESHOLD {
+            let mut offset = self.serialized_header_size();
+            for container in &self.containers {
+                writer.write_all(&(offset as u32).to_le_bytes())?;
+                offset += container.serialized_size();
+            }
+        }
+
+        for container in &self.containers {
+            container.serialize_into(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a bitmap written in the portable Roaring format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringBitMap;
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(10..20);
+    ///
+    /// let mut bytes = Vec::new();
+    /// bitmap.serialize_into(&mut bytes).unwrap();
+    /// let copy = RoaringBitMap::deserialize_from(&bytes[..]).unwrap();
+    /// assert_eq!(copy.len(), 10);
+    /// ```
+    pub fn deserialize_from<R: Read>(mut reader: R) -> io::Result<RoaringBitMap> {
+        let cookie = read_u32(&mut reader)?;
+        let (count, run_bitset) = if cookie == SERIAL_COOKIE_NO_RUNCONTAINER {
+            (read_u32(&mut reader)? as usize, None)
+        } else if cookie as u16 == SERIAL_COOKIE {
+            let count = (cookie >> 16) as usize + 1;
+            let mut run_bitset = vec![0u8; count.div_ceil(8)];
+            reader.read_exact(&mut run_bitset)?;
+            (count, Some(run_bitset))
+        } else {
+            return Err(invalid_data("unknown cookie"));
+        };
+        if count > 1 << 16 {
+            return Err(invalid_data("too many containers"));
+        }
+
+        let mut keys = Vec::with_capacity(count);
+        let mut lens = Vec::with_capacity(count);
+        for _ in 0..count {
+            let key = read_u16(&mut reader)?;
+            if keys.last().is_some_and(|&last| last >= key) {
+                return Err(invalid_data("keys are not sorted"));
+            }
+            keys.push(key);
+            lens.push(read_u16(&mut reader)? as usize + 1);
+        }
+
+        if run_bitset.is_none() || count >= NO_OFFSET_THRESHOLD {
+            // containers are stored in order, so the offsets are not needed
+            let mut offsets = vec![0u8; 4 * count];
+            reader.read_exact(&mut offsets)?;
+        }
+
+        let mut containers = Vec::with_capacity(count);
+        for (i, &len) in lens.iter().enumerate() {
+            let is_run = match run_bitset {
+                Some(ref run_bitset) => run_bitset[i / 8] & (1 << (i % 8)) != 0,
+                None => false,
+            };
+            let container = Container::deserialize_from(&mut reader, len, is_run)?;
+            containers.push(Box::new(container));
+        }
+
+        Ok(RoaringBitMap {
+            keys,
+            containers,
+            config: Config::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitset::BitSet;
+
+    use {Container, RoaringBitMap};
+
+    // from https://github.com/RoaringBitmap/RoaringFormatSpec/tree/master/testdata
+    static BITMAP_WITHOUT_RUNS: &[u8] =
+        include_bytes!("../tests/data/bitmapwithoutruns.bin");
+    static BITMAP_WITH_RUNS: &[u8] =
+        include_bytes!("../tests/data/bitmapwithruns.bin");
+
+    /// Returns the values of the bitmaps stored in the test data.
+    fn test_data_values() -> Vec<u32> {
+        (0..100)
+            .map(|v| v * 1000)
+            .chain((100000..200000).map(|v| v * 3))
+            .chain(700000..800000)
+            .collect()
+    }
+
+    fn round_trip(bitmap: &RoaringBitMap) -> RoaringBitMap {
+        let mut bytes = Vec::new();
+        bitmap.serialize_into(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), bitmap.serialized_size());
+        RoaringBitMap::deserialize_from(&bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn test_deserialize_test_data() {
+        for data in &[BITMAP_WITHOUT_RUNS, BITMAP_WITH_RUNS] {
+            let bitmap = RoaringBitMap::deserialize_from(*data).unwrap();
+            assert_eq!(bitmap.iter().collect::<Vec<u32>>(), test_data_values());
+        }
+    }
+
+    #[test]
+    fn test_serialize_test_data() {
+        for data in &[BITMAP_WITHOUT_RUNS, BITMAP_WITH_RUNS] {
+            let bitmap = RoaringBitMap::deserialize_from(*data).unwrap();
+            let mut bytes = Vec::new();
+            bitmap.serialize_into(&mut bytes).unwrap();
+            assert_eq!(bitmap.serialized_size(), data.len());
+            assert!(bytes == *data);
+        }
+
+        // run containers are chosen the same way as in the Java implementation
+        let mut bitmap = RoaringBitMap::deserialize_from(BITMAP_WITHOUT_RUNS)
+            .unwrap();
+        bitmap.run_optimize();
+        bitmap.validate().unwrap();
+        let mut bytes = Vec::new();
+        bitmap.serialize_into(&mut bytes).unwrap();
+        assert!(bytes == BITMAP_WITH_RUNS);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let empty = RoaringBitMap::new();
+        assert_eq!(empty.serialized_size(), 8);
+        assert!(round_trip(&empty).is_empty());
+
+        let mut bitmap = RoaringBitMap::new();
+        bitmap.insert_range(1000..5096);
+        bitmap.validate().unwrap();
+        bitmap.insert_range(70000..70100);
+        bitmap.validate().unwrap();
+        bitmap.keys.push(3);
+        let bitset = (0..=u16::MAX).step_by(3).collect::<BitSet>();
+        bitmap.containers.push(Box::new(Container::Dense(bitset)));
+        bitmap.insert(1 << 20);
+        bitmap.validate().unwrap();
+        let values = bitmap.iter().collect::<Vec<u32>>();
+        assert_eq!(round_trip(&bitmap).iter().collect::<Vec<u32>>(), values);
+
+        bitmap.run_optimize();
+        bitmap.validate().unwrap();
+        let copy = round_trip(&bitmap);
+        assert_eq!(copy.iter().collect::<Vec<u32>>(), values);
+        match *copy.containers[1] {
+            Container::Run(ref runs) => assert_eq!(*runs, vec![(4464, 99)]),
+            _ => panic!("expected a run container"),
+        }
+
+        // a dense container with exactly 4096 values is written as an array
+        let mut bitmap = RoaringBitMap::new();
+        bitmap.insert_range(1000..5096);
+        bitmap.validate().unwrap();
+        bitmap.remove_run_compression();
+        bitmap.validate().unwrap();
+        assert_eq!(bitmap.serialized_size(), 8 + 8 + 2 * 4096);
+        assert_eq!(round_trip(&bitmap).len(), 4096);
+    }
+
+    #[test]
+    fn test_deserialize_invalid_data() {
+        assert!(RoaringBitMap::deserialize_from(&[][..]).is_err());
+        assert!(RoaringBitMap::deserialize_from(&[0, 0, 0, 0][..]).is_err());
+        let truncated = &BITMAP_WITH_RUNS[..BITMAP_WITH_RUNS.len() - 1];
+        assert!(RoaringBitMap::deserialize_from(truncated).is_err());
+
+        // two containers with the same key
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&[0x3a, 0x30, 0, 0, 2, 0, 0, 0]);
+        bytes.extend_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0]);
+        bytes.extend_from_slice(&[24, 0, 0, 0, 26, 0, 0, 0]);
+        bytes.extend_from_slice(&[5, 0, 6, 0]);
+        assert!(RoaringBitMap::deserialize_from(&bytes[..]).is_err());
+        bytes[8] = 0;
+        let bitmap = RoaringBitMap::deserialize_from(&bytes[..]).unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![5, 65536 + 6]);
+    }
+}
@@ -0,0 +1,1542 @@
+//! A bitmap of 64-bit integers, made of one `RoaringBitMap` per distinct
+//! value of the high 32 bits.
+
+use std::collections::btree_map::{self, BTreeMap};
+use std::io::{self, Read, Write};
+use std::iter::Map;
+use std::ops::{Bound, RangeBounds};
+
+use serialization::invalid_data;
+use {IntoIter, Iter, RoaringBitMap};
+
+#[inline]
+fn split(value: u64) -> (u32, u32) {
+    ((value >> 32) as u32, value as u32)
+}
+
+#[inline]
+fn join(hi: u32, lo: u32) -> u64 {
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// Converts `range` to inclusive bounds, or `None` if it is empty.
+fn inclusive_bounds<R: RangeBounds<u64>>(range: &R) -> Option<(u64, u64)> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.checked_add(1)?,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end,
+        Bound::Excluded(&end) => end.checked_sub(1)?,
+        Bound::Unbounded => u64::MAX,
+    };
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// Returns the low 32 bits of the first and last values of the inclusive
+/// range `start..=end` whose high 32 bits are `hi`.
+fn low_bounds(hi: u32, start: u64, end: u64) -> (u32, u32) {
+    let (start_hi, start_lo) = split(start);
+    let (end_hi, end_lo) = split(end);
+    let lo = if hi == start_hi { start_lo } else { 0 };
+    let hi = if hi == end_hi { end_lo } else { u32::MAX };
+    (lo, hi)
+}
+
+/// A compressed bitmap of 64-bit integers.
+///
+/// Values sharing their high 32 bits are stored in the same `RoaringBitMap`,
+/// so that dense clusters of values are compressed as well as with 32-bit
+/// bitmaps.
+#[derive(Clone, Default)]
+pub struct RoaringTreemap {
+    // bitmaps are never empty
+    map: BTreeMap<u32, RoaringBitMap>,
+}
+
+impl RoaringTreemap {
+    /// Constructs a new `RoaringTreemap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// ```
+    pub fn new() -> RoaringTreemap {
+        RoaringTreemap { map: BTreeMap::new() }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.map.values().map(|bitmap| bitmap.len() as u64).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        let (hi, lo) = split(value);
+        match self.map.get(&hi) {
+            Some(bitmap) => bitmap.contains(lo),
+            None => false,
+        }
+    }
+
+    /// Adds `value` to the treemap, returning whether it was not already
+    /// present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// assert!(treemap.insert(1 << 40));
+    /// assert!(!treemap.insert(1 << 40));
+    /// assert!(treemap.contains(1 << 40));
+    /// ```
+    pub fn insert(&mut self, value: u64) -> bool {
+        let (hi, lo) = split(value);
+        self.map.entry(hi).or_default().insert(lo)
+    }
+
+    /// Removes `value` from the treemap, returning whether it was present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(1 << 40);
+    /// assert!(treemap.remove(1 << 40));
+    /// assert!(!treemap.remove(1 << 40));
+    /// assert!(treemap.is_empty());
+    /// ```
+    pub fn remove(&mut self, value: u64) -> bool {
+        let (hi, lo) = split(value);
+        let (removed, empty) = match self.map.get_mut(&hi) {
+            Some(bitmap) => (bitmap.remove(lo), bitmap.is_empty()),
+            None => return false,
+        };
+        if empty {
+            self.map.remove(&hi);
+        }
+        removed
+    }
+
+    /// Returns the smallest value of the treemap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// assert_eq!(treemap.min(), None);
+    /// treemap.insert(1 << 40);
+    /// treemap.insert(3);
+    /// assert_eq!(treemap.min(), Some(3));
+    /// ```
+    pub fn min(&self) -> Option<u64> {
+        self.map.iter().next()
+            .and_then(|(&hi, bitmap)| bitmap.min().map(|lo| join(hi, lo)))
+    }
+
+    /// Returns the greatest value of the treemap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// assert_eq!(treemap.max(), None);
+    /// treemap.insert(1 << 40);
+    /// treemap.insert(3);
+    /// assert_eq!(treemap.max(), Some(1 << 40));
+    /// ```
+    pub fn max(&self) -> Option<u64> {
+        self.map.iter().next_back()
+            .and_then(|(&hi, bitmap)| bitmap.max().map(|lo| join(hi, lo)))
+    }
+
+    /// Removes and returns the smallest value of the treemap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(1 << 40);
+    /// treemap.insert(3);
+    /// assert_eq!(treemap.pop_min(), Some(3));
+    /// assert_eq!(treemap.pop_min(), Some(1 << 40));
+    /// assert_eq!(treemap.pop_min(), None);
+    /// ```
+    pub fn pop_min(&mut self) -> Option<u64> {
+        let mut entry = self.map.first_entry()?;
+        let hi = *entry.key();
+        let lo = entry.get_mut().pop_min()?;
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        Some(join(hi, lo))
+    }
+
+    /// Removes and returns the greatest value of the treemap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(1 << 40);
+    /// treemap.insert(3);
+    /// assert_eq!(treemap.pop_max(), Some(1 << 40));
+    /// assert_eq!(treemap.pop_max(), Some(3));
+    /// assert_eq!(treemap.pop_max(), None);
+    /// ```
+    pub fn pop_max(&mut self) -> Option<u64> {
+        let mut entry = self.map.last_entry()?;
+        let hi = *entry.key();
+        let lo = entry.get_mut().pop_max()?;
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        Some(join(hi, lo))
+    }
+
+    /// Returns the number of values lower than or equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(3);
+    /// treemap.insert(1 << 40);
+    ///
+    /// assert_eq!(treemap.rank(2), 0);
+    /// assert_eq!(treemap.rank(3), 1);
+    /// assert_eq!(treemap.rank(u64::MAX), 2);
+    /// ```
+    pub fn rank(&self, value: u64) -> u64 {
+        let (hi, lo) = split(value);
+        let preceding = self.map.range(..hi).map(|(_, bitmap)| bitmap.len() as u64);
+        let rank = self.map.get(&hi).map_or(0, |bitmap| bitmap.rank(lo));
+        preceding.sum::<u64>() + rank
+    }
+
+    /// Returns the `n`-th smallest value of the treemap, counting from zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(3);
+    /// treemap.insert(1 << 40);
+    ///
+    /// assert_eq!(treemap.select(0), Some(3));
+    /// assert_eq!(treemap.select(1), Some(1 << 40));
+    /// assert_eq!(treemap.select(2), None);
+    /// ```
+    pub fn select(&self, n: u64) -> Option<u64> {
+        let mut n = n;
+        for (&hi, bitmap) in &self.map {
+            let len = bitmap.len() as u64;
+            if n < len {
+                return bitmap.select(n).map(|lo| join(hi, lo));
+            }
+            n -= len;
+        }
+        None
+    }
+
+    /// Returns the number of values in `range`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert_range((1 << 32) - 10..(1 << 32) + 10);
+    ///
+    /// assert_eq!(treemap.range_cardinality(..1 << 32), 10);
+    /// assert_eq!(treemap.range_cardinality(..), 20);
+    /// ```
+    pub fn range_cardinality<R: RangeBounds<u64>>(&self, range: R) -> u64 {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return 0,
+        };
+        let mut len = 0;
+        for (&hi, bitmap) in self.map.range(split(start).0..=split(end).0) {
+            let (lo, lo_end) = low_bounds(hi, start, end);
+            len += bitmap.range_cardinality(lo..=lo_end);
+        }
+        len
+    }
+
+    /// Returns true if all values of `range` are present in the treemap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert_range((1 << 32) - 10..(1 << 32) + 10);
+    ///
+    /// assert!(treemap.contains_range((1 << 32) - 10..(1 << 32) + 10));
+    /// assert!(!treemap.contains_range((1 << 32) - 11..(1 << 32) + 10));
+    /// ```
+    pub fn contains_range<R: RangeBounds<u64>>(&self, range: R) -> bool {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return true,
+        };
+        let (start_hi, end_hi) = (split(start).0, split(end).0);
+        let mut expected = start_hi as u64;
+        for (&hi, bitmap) in self.map.range(start_hi..=end_hi) {
+            let (lo, lo_end) = low_bounds(hi, start, end);
+            if hi as u64 != expected || !bitmap.contains_range(lo..=lo_end) {
+                return false;
+            }
+            expected += 1;
+        }
+        expected == end_hi as u64 + 1
+    }
+
+    /// Returns true if any value of `range` is present in the treemap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(1 << 40);
+    ///
+    /// assert!(treemap.intersects_range(..=1 << 40));
+    /// assert!(!treemap.intersects_range((1 << 40) + 1..));
+    /// ```
+    pub fn intersects_range<R: RangeBounds<u64>>(&self, range: R) -> bool {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return false,
+        };
+        self.map.range(split(start).0..=split(end).0).any(|(&hi, bitmap)| {
+            let (lo, lo_end) = low_bounds(hi, start, end);
+            bitmap.intersects_range(lo..=lo_end)
+        })
+    }
+
+    /// Inserts all values of `range`, returning how many were not already
+    /// present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(1 << 32);
+    ///
+    /// assert_eq!(treemap.insert_range((1 << 32) - 10..(1 << 32) + 10), 19);
+    /// assert_eq!(treemap.len(), 20);
+    /// ```
+    pub fn insert_range<R: RangeBounds<u64>>(&mut self, range: R) -> u64 {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return 0,
+        };
+        let mut inserted = 0;
+        for hi in split(start).0 as u64..split(end).0 as u64 + 1 {
+            let hi = hi as u32;
+            let (lo, lo_end) = low_bounds(hi, start, end);
+            let bitmap = self.map.entry(hi).or_default();
+            inserted += bitmap.insert_range(lo..=lo_end);
+        }
+        inserted
+    }
+
+    /// Removes all values of `range`, returning how many were present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert_range((1 << 32) - 10..(1 << 32) + 10);
+    ///
+    /// assert_eq!(treemap.remove_range(1 << 32..), 10);
+    /// assert_eq!(treemap.len(), 10);
+    /// ```
+    pub fn remove_range<R: RangeBounds<u64>>(&mut self, range: R) -> u64 {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return 0,
+        };
+        let mut removed = 0;
+        let mut emptied = Vec::new();
+        for (&hi, bitmap) in self.map.range_mut(split(start).0..=split(end).0) {
+            let (lo, lo_end) = low_bounds(hi, start, end);
+            removed += bitmap.remove_range(lo..=lo_end);
+            if bitmap.is_empty() {
+                emptied.push(hi);
+            }
+        }
+        for hi in emptied {
+            self.map.remove(&hi);
+        }
+        removed
+    }
+
+    /// Toggles every value of `range`: values present in the treemap are
+    /// removed and the missing ones are inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert_range((1 << 32) - 10..1 << 32);
+    ///
+    /// treemap.flip_inplace((1 << 32) - 5..(1 << 32) + 5);
+    /// assert_eq!(treemap.len(), 10);
+    /// assert!(treemap.contains((1 << 32) - 6));
+    /// assert!(!treemap.contains((1 << 32) - 5));
+    /// assert!(treemap.contains((1 << 32) + 4));
+    /// ```
+    pub fn flip_inplace<R: RangeBounds<u64>>(&mut self, range: R) {
+        let (start, end) = match inclusive_bounds(&range) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        for hi in split(start).0 as u64..split(end).0 as u64 + 1 {
+            let hi = hi as u32;
+            let (lo, lo_end) = low_bounds(hi, start, end);
+            let bitmap = self.map.entry(hi).or_default();
+            bitmap.flip_inplace(lo..=lo_end);
+            if bitmap.is_empty() {
+                self.map.remove(&hi);
+            }
+        }
+    }
+
+    /// Returns a new treemap with every value of `range` toggled.
+    pub fn flip<R: RangeBounds<u64>>(&self, range: R) -> RoaringTreemap {
+        let mut result = self.clone();
+        result.flip_inplace(range);
+        result
+    }
+
+    /// Adds all values of `other` to `self`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert(1);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert(1 << 40);
+    ///
+    /// a.union_with(&b);
+    /// assert!(a.contains(1));
+    /// assert!(a.contains(1 << 40));
+    /// ```
+    pub fn union_with(&mut self, other: &RoaringTreemap) {
+        for (&hi, bitmap) in &other.map {
+            match self.map.entry(hi) {
+                btree_map::Entry::Occupied(mut entry) => {
+                    entry.get_mut().union_with(bitmap);
+                }
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(bitmap.clone());
+                }
+            }
+        }
+    }
+
+    /// Returns a new treemap holding the values present in `self` or
+    /// `other`.
+    pub fn union(&self, other: &RoaringTreemap) -> RoaringTreemap {
+        let mut result = self.clone();
+        result.union_with(other);
+        result
+    }
+
+    /// Removes the values of `self` which are not present in `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert(1);
+    /// a.insert(1 << 40);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert(1 << 40);
+    ///
+    /// a.intersect_with(&b);
+    /// assert_eq!(a.len(), 1);
+    /// assert!(a.contains(1 << 40));
+    /// ```
+    pub fn intersect_with(&mut self, other: &RoaringTreemap) {
+        self.map.retain(|hi, bitmap| match other.map.get(hi) {
+            Some(other_bitmap) => {
+                bitmap.intersect_with(other_bitmap);
+                !bitmap.is_empty()
+            }
+            None => false,
+        });
+    }
+
+    /// Returns a new treemap holding the values present in both `self` and
+    /// `other`.
+    pub fn intersection(&self, other: &RoaringTreemap) -> RoaringTreemap {
+        let mut map = BTreeMap::new();
+        for (&hi, bitmap) in &self.map {
+            if let Some(other_bitmap) = other.map.get(&hi) {
+                let bitmap = bitmap.intersection(other_bitmap);
+                if !bitmap.is_empty() {
+                    map.insert(hi, bitmap);
+                }
+            }
+        }
+        RoaringTreemap { map }
+    }
+
+    /// Removes the values of `self` which are present in `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert(1);
+    /// a.insert(1 << 40);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert(1 << 40);
+    ///
+    /// a.difference_with(&b);
+    /// assert_eq!(a.len(), 1);
+    /// assert!(a.contains(1));
+    /// ```
+    pub fn difference_with(&mut self, other: &RoaringTreemap) {
+        for (&hi, other_bitmap) in &other.map {
+            let empty = match self.map.get_mut(&hi) {
+                Some(bitmap) => {
+                    bitmap.difference_with(other_bitmap);
+                    bitmap.is_empty()
+                }
+                None => false,
+            };
+            if empty {
+                self.map.remove(&hi);
+            }
+        }
+    }
+
+    /// Returns a new treemap holding the values present in `self` but not in
+    /// `other`.
+    pub fn difference(&self, other: &RoaringTreemap) -> RoaringTreemap {
+        let mut result = self.clone();
+        result.difference_with(other);
+        result
+    }
+
+    /// Keeps the values present in exactly one of `self` and `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert(1);
+    /// a.insert(1 << 40);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert(1 << 40);
+    /// b.insert(1 << 50);
+    ///
+    /// a.symmetric_difference_with(&b);
+    /// assert_eq!(a.iter().collect::<Vec<u64>>(), vec![1, 1 << 50]);
+    /// ```
+    pub fn symmetric_difference_with(&mut self, other: &RoaringTreemap) {
+        for (&hi, other_bitmap) in &other.map {
+            let empty = match self.map.entry(hi) {
+                btree_map::Entry::Occupied(mut entry) => {
+                    entry.get_mut().symmetric_difference_with(other_bitmap);
+                    entry.get().is_empty()
+                }
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(other_bitmap.clone());
+                    false
+                }
+            };
+            if empty {
+                self.map.remove(&hi);
+            }
+        }
+    }
+
+    /// Returns a new treemap holding the values present in exactly one of
+    /// `self` and `other`.
+    pub fn symmetric_difference(&self, other: &RoaringTreemap) -> RoaringTreemap {
+        let mut result = self.clone();
+        result.symmetric_difference_with(other);
+        result
+    }
+
+    /// Returns true if all values of `self` are present in `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert(1);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert(1);
+    /// b.insert(1 << 40);
+    ///
+    /// assert!(a.is_subset(&b));
+    /// assert!(!b.is_subset(&a));
+    /// ```
+    pub fn is_subset(&self, other: &RoaringTreemap) -> bool {
+        self.map.iter().all(|(hi, bitmap)| match other.map.get(hi) {
+            Some(other_bitmap) => bitmap.is_subset(other_bitmap),
+            None => false,
+        })
+    }
+
+    /// Returns true if all values of `other` are present in `self`.
+    pub fn is_superset(&self, other: &RoaringTreemap) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Returns true if `self` and `other` have at least one value in common.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert(1);
+    /// a.insert(1 << 40);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert(1 << 40);
+    ///
+    /// assert!(a.intersects(&b));
+    /// b.remove(1 << 40);
+    /// assert!(!a.intersects(&b));
+    /// ```
+    pub fn intersects(&self, other: &RoaringTreemap) -> bool {
+        self.map.iter().any(|(hi, bitmap)| match other.map.get(hi) {
+            Some(other_bitmap) => bitmap.intersects(other_bitmap),
+            None => false,
+        })
+    }
+
+    /// Returns true if `self` and `other` have no value in common.
+    pub fn is_disjoint(&self, other: &RoaringTreemap) -> bool {
+        !self.intersects(other)
+    }
+
+    /// Returns the number of values present in both `self` and `other`,
+    /// without building their intersection.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert_range((1 << 32) - 50..(1 << 32) + 50);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert_range(1 << 32..(1 << 32) + 100);
+    ///
+    /// assert_eq!(a.intersection_len(&b), 50);
+    /// ```
+    pub fn intersection_len(&self, other: &RoaringTreemap) -> u64 {
+        self.map.iter()
+            .filter_map(|(hi, bitmap)| {
+                other.map.get(hi).map(|other| bitmap.intersection_len(other))
+            })
+            .sum()
+    }
+
+    /// Returns the number of values present in `self` or `other`, without
+    /// building their union.
+    pub fn union_len(&self, other: &RoaringTreemap) -> u64 {
+        self.len() + other.len() - self.intersection_len(other)
+    }
+
+    /// Returns the number of values present in `self` but not in `other`,
+    /// without building their difference.
+    pub fn difference_len(&self, other: &RoaringTreemap) -> u64 {
+        self.len() - self.intersection_len(other)
+    }
+
+    /// Returns the number of values present in exactly one of `self` and
+    /// `other`, without building their symmetric difference.
+    pub fn symmetric_difference_len(&self, other: &RoaringTreemap) -> u64 {
+        self.len() + other.len() - 2 * self.intersection_len(other)
+    }
+
+    /// Returns the Jaccard index of `self` and `other`, that is the size of
+    /// their intersection divided by the size of their union. Two empty
+    /// treemaps have an index of 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut a = RoaringTreemap::new();
+    /// a.insert_range((1 << 32) - 50..(1 << 32) + 50);
+    /// let mut b = RoaringTreemap::new();
+    /// b.insert_range(1 << 32..(1 << 32) + 100);
+    ///
+    /// assert_eq!(a.jaccard_index(&b), 1.0 / 3.0);
+    /// ```
+    pub fn jaccard_index(&self, other: &RoaringTreemap) -> f64 {
+        let intersection_len = self.intersection_len(other);
+        let union_len = self.len() + other.len() - intersection_len;
+        if union_len == 0 {
+            1.0
+        } else {
+            intersection_len as f64 / union_len as f64
+        }
+    }
+
+    /// Converts every container to the smallest of the run, sparse and dense
+    /// representations. Returns whether any container changed.
+    pub fn run_optimize(&mut self) -> bool {
+        let mut changed = false;
+        for bitmap in self.map.values_mut() {
+            changed |= bitmap.run_optimize();
+        }
+        changed
+    }
+
+    /// Converts every run container back to a sparse or dense one. Returns
+    /// whether any container changed.
+    pub fn remove_run_compression(&mut self) -> bool {
+        let mut changed = false;
+        for bitmap in self.map.values_mut() {
+            changed |= bitmap.remove_run_compression();
+        }
+        changed
+    }
+
+    /// Checks the internal invariants of the treemap: no bitmap is empty and
+    /// every bitmap is valid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert_range((1 << 32) - 5000..(1 << 32) + 5000);
+    /// treemap.remove(1 << 32);
+    /// assert!(treemap.validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<(), String> {
+        for (&hi, bitmap) in &self.map {
+            if bitmap.is_empty() {
+                return Err(format!("bitmap {} is empty", hi));
+            }
+            bitmap.validate().map_err(|message| format!("bitmap {}: {}", hi, message))?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the values of the treemap in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(1 << 40);
+    /// treemap.insert(3);
+    ///
+    /// let values = treemap.iter().collect::<Vec<u64>>();
+    /// assert_eq!(values, vec![3, 1 << 40]);
+    /// ```
+    pub fn iter<'a>(&'a self) -> TreemapIter<'a> {
+        TreemapIter {
+            inner: Inner::new(self.map.iter().map(borrowed_bitmap), self.len()),
+        }
+    }
+
+    /// Returns the number of bytes written by `serialize_into`.
+    pub fn serialized_size(&self) -> usize {
+        8 + self.map.values().map(|bitmap| 4 + bitmap.serialized_size()).sum::<usize>()
+    }
+
+    /// Writes the treemap in the 64-bit portable format shared with the Java
+    /// and C implementations: the number of bitmaps as a 64-bit integer, then
+    /// each bitmap preceded by its high 32 bits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert(1 << 40);
+    ///
+    /// let mut bytes = Vec::new();
+    /// treemap.serialize_into(&mut bytes).unwrap();
+    /// assert_eq!(bytes.len(), treemap.serialized_size());
+    /// let copy = RoaringTreemap::deserialize_from(&bytes[..]).unwrap();
+    /// assert!(copy.contains(1 << 40));
+    /// ```
+    pub fn serialize_into<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&(self.map.len() as u64).to_le_bytes())?;
+        for (&hi, bitmap) in &self.map {
+            writer.write_all(&hi.to_le_bytes())?;
+            bitmap.serialize_into(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a treemap written in the 64-bit portable format.
+    pub fn deserialize_from<R: Read>(mut reader: R) -> io::Result<RoaringTreemap> {
+        let mut buf = [0; 8];
+        reader.read_exact(&mut buf)?;
+        let count = u64::from_le_bytes(buf);
+        let mut map = BTreeMap::new();
+        let mut prev: Option<u32> = None;
+        for _ in 0..count {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            let hi = u32::from_le_bytes(buf);
+            if prev.is_some_and(|prev| prev >= hi) {
+                return Err(invalid_data("keys are not sorted"));
+            }
+            prev = Some(hi);
+            let bitmap = RoaringBitMap::deserialize_from(&mut reader)?;
+            if !bitmap.is_empty() {
+                map.insert(hi, bitmap);
+            }
+        }
+        Ok(RoaringTreemap { map })
+    }
+}
+
+fn borrowed_bitmap<'a>((&hi, bitmap): (&'a u32, &'a RoaringBitMap)) -> (u32, Iter<'a>) {
+    (hi, bitmap.iter())
+}
+
+fn owned_bitmap((hi, bitmap): (u32, RoaringBitMap)) -> (u32, IntoIter) {
+    (hi, bitmap.into_iter())
+}
+
+/// An iterator over the values of one of the bitmaps of a treemap.
+trait BitmapIter: DoubleEndedIterator<Item = u32> + ExactSizeIterator {
+    fn advance_to(&mut self, value: u32);
+    fn advance_back_to(&mut self, value: u32);
+}
+
+impl<'a> BitmapIter for Iter<'a> {
+    fn advance_to(&mut self, value: u32) {
+        Iter::advance_to(self, value);
+    }
+
+    fn advance_back_to(&mut self, value: u32) {
+        Iter::advance_back_to(self, value);
+    }
+}
+
+impl BitmapIter for IntoIter {
+    fn advance_to(&mut self, value: u32) {
+        IntoIter::advance_to(self, value);
+    }
+
+    fn advance_back_to(&mut self, value: u32) {
+        IntoIter::advance_back_to(self, value);
+    }
+}
+
+/// Applies `advance` to `values`, subtracting the skipped values from `len`.
+fn advance_bitmap<I, F>(len: &mut u64, values: &mut I, advance: F)
+    where I: BitmapIter,
+          F: FnOnce(&mut I)
+{
+    let before = values.len();
+    advance(values);
+    *len -= (before - values.len()) as u64;
+}
+
+/// Chains the iterators over the bitmaps of a treemap, from both ends.
+struct Inner<O, I> {
+    bitmaps: O,
+    front: Option<(u32, I)>,
+    back: Option<(u32, I)>,
+    len: u64,
+}
+
+impl<O, I> Inner<O, I>
+    where O: DoubleEndedIterator<Item = (u32, I)>,
+          I: BitmapIter
+{
+    fn new(bitmaps: O, len: u64) -> Inner<O, I> {
+        Inner {
+            bitmaps,
+            front: None,
+            back: None,
+            len,
+        }
+    }
+
+    fn next(&mut self) -> Option<u64> {
+        loop {
+            if let Some((hi, ref mut values)) = self.front {
+                if let Some(lo) = values.next() {
+                    self.len -= 1;
+                    return Some(join(hi, lo));
+                }
+            }
+            match self.bitmaps.next() {
+                Some(bitmap) => self.front = Some(bitmap),
+                // the back iterator may hold the last values
+                None => {
+                    self.front = None;
+                    return match self.back {
+                        Some((hi, ref mut values)) => values.next().map(|lo| {
+                            self.len -= 1;
+                            join(hi, lo)
+                        }),
+                        None => None,
+                    };
+                }
+            }
+        }
+    }
+
+    fn next_back(&mut self) -> Option<u64> {
+        loop {
+            if let Some((hi, ref mut values)) = self.back {
+                if let Some(lo) = values.next_back() {
+                    self.len -= 1;
+                    return Some(join(hi, lo));
+                }
+            }
+            match self.bitmaps.next_back() {
+                Some(bitmap) => self.back = Some(bitmap),
+                None => {
+                    self.back = None;
+                    return match self.front {
+                        Some((hi, ref mut values)) => values.next_back().map(|lo| {
+                            self.len -= 1;
+                            join(hi, lo)
+                        }),
+                        None => None,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Skips the values lower than `value` from the front.
+    fn advance_to(&mut self, value: u64) {
+        let (hi, lo) = split(value);
+        // the bitmaps up to the one holding `value` are skipped entirely
+        while let Some((key, mut values)) = self.front.take()
+            .or_else(|| self.bitmaps.next()) {
+            if key >= hi {
+                if key == hi {
+                    advance_bitmap(&mut self.len, &mut values,
+                                   |values| values.advance_to(lo));
+                }
+                self.front = Some((key, values));
+                return;
+            }
+            self.len -= values.len() as u64;
+        }
+        // the remaining values are all in the back iterator
+        if let Some((key, ref mut values)) = self.back {
+            if key < hi {
+                self.len -= values.len() as u64;
+                self.back = None;
+            } else if key == hi {
+                advance_bitmap(&mut self.len, values, |values| values.advance_to(lo));
+            }
+        }
+    }
+
+    /// Skips the values greater than `value` from the back.
+    fn advance_back_to(&mut self, value: u64) {
+        let (hi, lo) = split(value);
+        // the bitmaps up to the one holding `value` are skipped entirely
+        while let Some((key, mut values)) = self.back.take()
+            .or_else(|| self.bitmaps.next_back()) {
+            if key <= hi {
+                if key == hi {
+                    advance_bitmap(&mut self.len, &mut values,
+                                   |values| values.advance_back_to(lo));
+                }
+                self.back = Some((key, values));
+                return;
+            }
+            self.len -= values.len() as u64;
+        }
+        // the remaining values are all in the front iterator
+        if let Some((key, ref mut values)) = self.front {
+            if key > hi {
+                self.len -= values.len() as u64;
+                self.front = None;
+            } else if key == hi {
+                advance_bitmap(&mut self.len, values,
+                               |values| values.advance_back_to(lo));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.len <= usize::MAX as u64 {
+            (self.len as usize, Some(self.len as usize))
+        } else {
+            (usize::MAX, None)
+        }
+    }
+}
+
+type BorrowedBitmaps<'a> =
+    Map<btree_map::Iter<'a, u32, RoaringBitMap>,
+        fn((&'a u32, &'a RoaringBitMap)) -> (u32, Iter<'a>)>;
+
+type OwnedBitmaps =
+    Map<btree_map::IntoIter<u32, RoaringBitMap>,
+        fn((u32, RoaringBitMap)) -> (u32, IntoIter)>;
+
+/// An iterator over the values of a `RoaringTreemap` in ascending order.
+pub struct TreemapIter<'a> {
+    inner: Inner<BorrowedBitmaps<'a>, Iter<'a>>,
+}
+
+/// An owning iterator over the values of a `RoaringTreemap` in ascending
+/// order.
+pub struct TreemapIntoIter {
+    inner: Inner<OwnedBitmaps, IntoIter>,
+}
+
+impl<'a> TreemapIter<'a> {
+    /// Skips the values lower than `value`, so that the next value returned
+    /// from the front is the smallest one greater than or equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert_range((1 << 32) - 2..(1 << 32) + 2);
+    ///
+    /// let mut iter = treemap.iter();
+    /// iter.advance_to(1 << 32);
+    /// assert_eq!(iter.next(), Some(1 << 32));
+    /// assert_eq!(iter.next(), Some((1 << 32) + 1));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn advance_to(&mut self, value: u64) {
+        self.inner.advance_to(value);
+    }
+
+    /// Skips the values greater than `value`, so that the next value returned
+    /// from the back is the greatest one lower than or equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::RoaringTreemap;
+    ///
+    /// let mut treemap = RoaringTreemap::new();
+    /// treemap.insert_range((1 << 32) - 2..(1 << 32) + 2);
+    ///
+    /// let mut iter = treemap.iter();
+    /// iter.advance_back_to((1 << 32) - 1);
+    /// assert_eq!(iter.next_back(), Some((1 << 32) - 1));
+    /// assert_eq!(iter.next_back(), Some((1 << 32) - 2));
+    /// assert_eq!(iter.next_back(), None);
+    /// ```
+    pub fn advance_back_to(&mut self, value: u64) {
+        self.inner.advance_back_to(value);
+    }
+}
+
+impl TreemapIntoIter {
+    /// Skips the values lower than `value`, so that the next value returned
+    /// from the front is the smallest one greater than or equal to `value`.
+    pub fn advance_to(&mut self, value: u64) {
+        self.inner.advance_to(value);
+    }
+
+    /// Skips the values greater than `value`, so that the next value returned
+    /// from the back is the greatest one lower than or equal to `value`.
+    pub fn advance_back_to(&mut self, value: u64) {
+        self.inner.advance_back_to(value);
+    }
+}
+
+impl<'a> Iterator for TreemapIter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for TreemapIter<'a> {
+    fn next_back(&mut self) -> Option<u64> {
+        self.inner.next_back()
+    }
+}
+
+impl Iterator for TreemapIntoIter {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for TreemapIntoIter {
+    fn next_back(&mut self) -> Option<u64> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a> IntoIterator for &'a RoaringTreemap {
+    type Item = u64;
+    type IntoIter = TreemapIter<'a>;
+
+    fn into_iter(self) -> TreemapIter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for RoaringTreemap {
+    type Item = u64;
+    type IntoIter = TreemapIntoIter;
+
+    fn into_iter(self) -> TreemapIntoIter {
+        let len = self.len();
+        TreemapIntoIter { inner: Inner::new(self.map.into_iter().map(owned_bitmap), len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoaringTreemap;
+    use RoaringBitMap;
+
+    /// Returns a treemap with values around several 32-bit boundaries, along
+    /// with its values in ascending order.
+    fn treemap() -> (RoaringTreemap, Vec<u64>) {
+        let mut treemap = RoaringTreemap::new();
+        let mut values = Vec::new();
+        for &base in &[0, 1 << 32, 5 << 32, u64::MAX - 99] {
+            for value in (base..=base + 99).step_by(3) {
+                treemap.insert(value);
+                treemap.validate().unwrap();
+                values.push(value);
+            }
+        }
+        treemap.insert_range((3 << 32) - 5..(3 << 32) + 5);
+        treemap.validate().unwrap();
+        values.extend((3 << 32) - 5..(3 << 32) + 5);
+        values.sort();
+        (treemap, values)
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let (mut treemap, values) = treemap();
+        assert_eq!(treemap.len(), values.len() as u64);
+        assert_eq!(treemap.iter().collect::<Vec<u64>>(), values);
+        assert_eq!(treemap.min(), Some(0));
+        assert_eq!(treemap.max(), Some(u64::MAX));
+        assert!(treemap.contains(5 << 32));
+        assert!(!treemap.contains((5 << 32) + 1));
+        assert!(!treemap.insert(5 << 32));
+        treemap.validate().unwrap();
+        for &value in &values {
+            assert!(treemap.remove(value));
+            treemap.validate().unwrap();
+        }
+        assert!(!treemap.remove(0));
+        treemap.validate().unwrap();
+        assert!(treemap.is_empty());
+        assert!(treemap.map.is_empty());
+
+        treemap.map.insert(3, RoaringBitMap::new());
+        assert_eq!(treemap.validate(), Err("bitmap 3 is empty".to_string()));
+    }
+
+    #[test]
+    fn test_iter() {
+        let (treemap, values) = treemap();
+        assert_eq!(treemap.iter().size_hint(), (values.len(), Some(values.len())));
+        let mut rev = values.clone();
+        rev.reverse();
+        assert_eq!(treemap.iter().rev().collect::<Vec<u64>>(), rev);
+        assert_eq!(treemap.clone().into_iter().collect::<Vec<u64>>(), values);
+        assert_eq!(treemap.clone().into_iter().rev().collect::<Vec<u64>>(), rev);
+
+        // alternate between both ends, meeting inside a bitmap
+        let mut iter = treemap.iter();
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some(value) = iter.next() {
+            front.push(value);
+            match iter.next_back() {
+                Some(value) => back.push(value),
+                None => break,
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, values);
+    }
+
+    #[test]
+    fn test_advance() {
+        let (treemap, values) = treemap();
+        for &target in &[0, 50, 1 << 32, (1 << 32) + 100, (3 << 32) - 1, u64::MAX] {
+            let expected = values.iter().cloned().filter(|&v| v >= target);
+            let expected = expected.collect::<Vec<u64>>();
+            let mut iter = treemap.iter();
+            iter.advance_to(target);
+            assert_eq!(iter.size_hint().0, expected.len());
+            assert_eq!(iter.collect::<Vec<u64>>(), expected);
+            let mut into_iter = treemap.clone().into_iter();
+            into_iter.advance_to(target);
+            assert_eq!(into_iter.collect::<Vec<u64>>(), expected);
+
+            let expected = values.iter().cloned().rev().filter(|&v| v <= target);
+            let expected = expected.collect::<Vec<u64>>();
+            let mut iter = treemap.iter();
+            iter.advance_back_to(target);
+            assert_eq!(iter.size_hint().0, expected.len());
+            assert_eq!(iter.rev().collect::<Vec<u64>>(), expected);
+            let mut into_iter = treemap.clone().into_iter();
+            into_iter.advance_back_to(target);
+            assert_eq!(into_iter.rev().collect::<Vec<u64>>(), expected);
+        }
+        assert_eq!((&treemap).into_iter().collect::<Vec<u64>>(), values);
+        let mut looped = Vec::new();
+        for value in &treemap {
+            looped.push(value);
+        }
+        assert_eq!(looped, values);
+
+        // advancing past the values held by the other end
+        let mut iter = treemap.iter();
+        assert_eq!(iter.next_back(), Some(u64::MAX));
+        iter.advance_to(u64::MAX - 1);
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+        let mut iter = treemap.iter();
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.next_back(), Some(u64::MAX));
+        iter.advance_back_to((5 << 32) + 7);
+        assert_eq!(iter.next_back(), Some((5 << 32) + 6));
+        iter.advance_to((5 << 32) + 1);
+        assert_eq!(iter.next(), Some((5 << 32) + 3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_rank_select() {
+        let (treemap, values) = treemap();
+        for (i, &value) in values.iter().enumerate() {
+            assert_eq!(treemap.rank(value), i as u64 + 1);
+            assert_eq!(treemap.select(i as u64), Some(value));
+        }
+        assert_eq!(treemap.rank((2 << 32) + 7), 68);
+        assert_eq!(treemap.select(values.len() as u64), None);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut treemap = RoaringTreemap::new();
+        let (start, end) = ((1 << 32) - 10, (3 << 32) + 10);
+        assert_eq!(treemap.insert_range(start..end), 2 * (1 << 32) + 20);
+        treemap.validate().unwrap();
+        assert_eq!(treemap.map.len(), 4);
+        assert!(treemap.contains_range(start..end));
+        assert!(!treemap.contains_range(start..=end));
+        assert!(treemap.intersects_range(end - 1..));
+        assert!(!treemap.intersects_range(end..));
+        assert_eq!(treemap.range_cardinality(start + 5..=start + 14), 10);
+
+        assert_eq!(treemap.remove_range(1 << 32..3 << 32), 2 * (1 << 32));
+        treemap.validate().unwrap();
+        assert_eq!(treemap.map.len(), 2);
+        assert_eq!(treemap.len(), 20);
+        assert!(!treemap.contains_range(start..end));
+        assert!(treemap.contains_range(start..1 << 32));
+        assert_eq!(treemap.remove_range(..), 20);
+        treemap.validate().unwrap();
+        assert!(treemap.is_empty());
+
+        assert_eq!(treemap.insert_range(u64::MAX - 1..), 2);
+        treemap.validate().unwrap();
+        assert!(treemap.contains(u64::MAX));
+        assert_eq!(treemap.range_cardinality(..), 2);
+        assert_eq!(treemap.insert_range(5..5), 0);
+        treemap.validate().unwrap();
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let (a, a_values) = treemap();
+        let mut b = RoaringTreemap::new();
+        b.insert_range((1 << 32) + 50..(1 << 32) + 5000);
+        b.validate().unwrap();
+        b.insert_range((3 << 32) - 2..(3 << 32) + 2);
+        b.validate().unwrap();
+        b.insert(1 << 60);
+        b.validate().unwrap();
+        let b_values = b.iter().collect::<Vec<u64>>();
+
+        let expected = |keep: fn(bool, bool) -> bool| {
+            let mut values = a_values.iter()
+                .chain(b_values.iter())
+                .cloned()
+                .filter(|value| {
+                    keep(a_values.binary_search(value).is_ok(),
+                         b_values.binary_search(value).is_ok())
+                })
+                .collect::<Vec<u64>>();
+            values.sort();
+            values.dedup();
+            values
+        };
+        let values = |treemap: RoaringTreemap| {
+            treemap.validate().unwrap();
+            treemap.iter().collect::<Vec<u64>>()
+        };
+
+        assert_eq!(values(a.union(&b)), expected(|a, b| a || b));
+        assert_eq!(values(a.intersection(&b)), expected(|a, b| a && b));
+        assert_eq!(values(a.difference(&b)), expected(|a, b| a && !b));
+        assert_eq!(values(a.symmetric_difference(&b)), expected(|a, b| a != b));
+        assert_eq!(values(&a | &b), expected(|a, b| a || b));
+        assert_eq!(values(a.clone() & &b), expected(|a, b| a && b));
+        assert_eq!(values(&a - b.clone()), expected(|a, b| a && !b));
+        assert_eq!(values(a.clone() ^ b.clone()), expected(|a, b| a != b));
+
+        let mut c = a.clone();
+        c.intersect_with(&b);
+        c.validate().unwrap();
+        assert_eq!(c.map.len(), 3);
+        c.symmetric_difference_with(&c.clone());
+        c.validate().unwrap();
+        assert!(c.is_empty());
+        assert!(c.map.is_empty());
+
+        assert!(a.intersects(&b));
+        assert!(!a.is_subset(&b));
+        assert!(a.intersection(&b).is_subset(&b));
+        assert!(a.union(&b).is_superset(&a));
+        assert!(a.difference(&b).is_disjoint(&b));
+
+        let len = |keep: fn(bool, bool) -> bool| expected(keep).len() as u64;
+        assert_eq!(a.intersection_len(&b), len(|a, b| a && b));
+        assert_eq!(a.union_len(&b), len(|a, b| a || b));
+        assert_eq!(a.difference_len(&b), len(|a, b| a && !b));
+        assert_eq!(a.symmetric_difference_len(&b), len(|a, b| a != b));
+        assert_eq!(a.jaccard_index(&b),
+                   len(|a, b| a && b) as f64 / len(|a, b| a || b) as f64);
+        assert_eq!(a.jaccard_index(&a), 1.0);
+        assert_eq!(RoaringTreemap::new().jaccard_index(&RoaringTreemap::new()), 1.0);
+    }
+
+    #[test]
+    fn test_in_place_operations() {
+        let (mut a, values) = treemap();
+        let mut b = RoaringTreemap::default();
+        b.insert_range(1 << 32..(1 << 32) + 10);
+        b.validate().unwrap();
+        b.insert(1 << 60);
+        b.validate().unwrap();
+
+        a.union_with(&b);
+        a.validate().unwrap();
+        assert_eq!(a.len(), values.len() as u64 + 7);
+        assert!(a.contains(1 << 60));
+        a.difference_with(&b);
+        a.validate().unwrap();
+        assert_eq!(a.len(), values.len() as u64 - 4);
+        assert!(!a.contains(1 << 32));
+        b.difference_with(&b.clone());
+        b.validate().unwrap();
+        assert!(b.is_empty());
+        assert!(b.map.is_empty());
+
+        for value in 7 << 32..(7 << 32) + 100 {
+            a.insert(value);
+            a.validate().unwrap();
+        }
+        assert!(a.run_optimize());
+        a.validate().unwrap();
+        assert!(a.remove_run_compression());
+        a.validate().unwrap();
+        assert!(!a.remove_run_compression());
+        a.validate().unwrap();
+        assert_eq!(a.len(), values.len() as u64 + 96);
+        a.clear();
+        a.validate().unwrap();
+        assert!(a.is_empty());
+        assert_eq!(a.iter().next(), None);
+    }
+
+    #[test]
+    fn test_flip_pop() {
+        let (mut treemap, mut values) = treemap();
+        let range = (1 << 32) - 10..(5 << 32) + 10;
+        let inside = values.iter().filter(|&value| range.contains(value)).count() as u64;
+        treemap.flip_inplace(range.clone());
+        treemap.validate().unwrap();
+        assert_eq!(treemap.len(),
+                   values.len() as u64 + range.end - range.start - 2 * inside);
+        assert!(treemap.contains((2 << 32) + 12));
+        assert!(!treemap.contains(1 << 32));
+        assert!(treemap.contains((1 << 32) + 1));
+        treemap.flip_inplace(range);
+        treemap.validate().unwrap();
+        assert_eq!(treemap.iter().collect::<Vec<u64>>(), values);
+        let flipped = treemap.flip(10..(1 << 32) + 10);
+        assert_eq!(flipped.len(), treemap.len() + (1 << 32) - 2 * 34);
+        assert_eq!(flipped.flip(10..(1 << 32) + 10).iter().collect::<Vec<u64>>(), values);
+
+        assert_eq!(treemap.pop_min(), Some(values.remove(0)));
+        treemap.validate().unwrap();
+        assert_eq!(treemap.pop_max(), values.pop());
+        treemap.validate().unwrap();
+        while let Some(value) = treemap.pop_max() {
+            treemap.validate().unwrap();
+            assert_eq!(Some(value), values.pop());
+        }
+        assert!(values.is_empty());
+        assert!(treemap.map.is_empty());
+        assert_eq!(treemap.pop_min(), None);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let (mut treemap, values) = treemap();
+        treemap.run_optimize();
+        treemap.validate().unwrap();
+        let mut bytes = Vec::new();
+        treemap.serialize_into(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), treemap.serialized_size());
+        assert_eq!(&bytes[..8], &[6, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[8..12], &[0, 0, 0, 0]);
+
+        // the first bitmap is stored in the 32-bit portable format
+        let mut first = RoaringBitMap::new();
+        for value in (0..100).step_by(3) {
+            first.insert(value);
+            first.validate().unwrap();
+        }
+        let mut first_bytes = Vec::new();
+        first.serialize_into(&mut first_bytes).unwrap();
+        assert_eq!(&bytes[12..12 + first_bytes.len()], &first_bytes[..]);
+
+        let copy = RoaringTreemap::deserialize_from(&bytes[..]).unwrap();
+        copy.validate().unwrap();
+        assert_eq!(copy.iter().collect::<Vec<u64>>(), values);
+        for len in 0..bytes.len() {
+            assert!(RoaringTreemap::deserialize_from(&bytes[..len]).is_err());
+        }
+
+        let mut empty = Vec::new();
+        RoaringTreemap::new().serialize_into(&mut empty).unwrap();
+        assert_eq!(empty, vec![0; 8]);
+        assert!(RoaringTreemap::deserialize_from(&empty[..]).unwrap().is_empty());
+
+        // keys must be sorted
+        let mut unsorted = vec![2, 0, 0, 0, 0, 0, 0, 0];
+        for &hi in &[1u32, 0] {
+            unsorted.extend_from_slice(&hi.to_le_bytes());
+            first.serialize_into(&mut unsorted).unwrap();
+        }
+        assert!(RoaringTreemap::deserialize_from(&unsorted[..]).is_err());
+    }
+
+    #[test]
+    fn test_serialization_format() {
+        let mut treemap = RoaringTreemap::new();
+        treemap.insert(1);
+        treemap.validate().unwrap();
+        treemap.insert(2);
+        treemap.validate().unwrap();
+        treemap.insert((1 << 32) + 5);
+        treemap.validate().unwrap();
+        treemap.insert_range(2 << 32..(2 << 32) + 100);
+        treemap.validate().unwrap();
+        treemap.run_optimize();
+        treemap.validate().unwrap();
+
+        // a u64 count, then a u32 key and a 32-bit portable bitmap per entry
+        let expected: &[u8] = &[
+            3, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0,
+            0x3a, 0x30, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 16, 0, 0, 0, 1, 0, 2, 0,
+            1, 0, 0, 0,
+            0x3a, 0x30, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 5, 0,
+            2, 0, 0, 0,
+            0x3b, 0x30, 0, 0, 1, 0, 0, 99, 0, 1, 0, 0, 0, 99, 0,
+        ];
+        let mut bytes = Vec::new();
+        treemap.serialize_into(&mut bytes).unwrap();
+        assert_eq!(&bytes[..], expected);
+
+        let copy = RoaringTreemap::deserialize_from(expected).unwrap();
+        copy.validate().unwrap();
+        assert_eq!(copy.iter().collect::<Vec<u64>>(), vec![1, 2, (1 << 32) + 5]
+                   .into_iter().chain(2 << 32..(2 << 32) + 100).collect::<Vec<u64>>());
+    }
+}
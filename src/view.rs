@@ -0,0 +1,910 @@
+//! A read-only view over a bitmap serialized in the portable format.
+
+use bitset::{BitSet, WORD_COUNT};
+use std::cmp::{self, Ordering};
+use std::io;
+use std::iter;
+
+use serialization::{invalid_data, ARRAY_CONTAINER_MAX_LEN,
+                    BITMAP_CONTAINER_SIZE_IN_BYTES, NO_OFFSET_THRESHOLD,
+                    SERIAL_COOKIE, SERIAL_COOKIE_NO_RUNCONTAINER};
+use {key_val_pair, run, Config, Container, RoaringBitMap};
+
+#[inline]
+fn u16_at(bytes: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]])
+}
+
+#[inline]
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[4 * i..4 * i + 4]);
+    u32::from_le_bytes(buf)
+}
+
+#[inline]
+fn u64_at(bytes: &[u8], i: usize) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[8 * i..8 * i + 8]);
+    u64::from_le_bytes(buf)
+}
+
+/// Binary searches `value` in an array container.
+fn search(bytes: &[u8], value: u16) -> Result<usize, usize> {
+    let (mut lo, mut hi) = (0, bytes.len() / 2);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        match u16_at(bytes, mid).cmp(&value) {
+            Ordering::Less => lo = mid + 1,
+            Ordering::Greater => hi = mid,
+            Ordering::Equal => return Ok(mid),
+        }
+    }
+    Err(lo)
+}
+
+/// Returns the number of runs of a run container starting at or before
+/// `value`.
+fn search_runs(bytes: &[u8], value: u16) -> usize {
+    let (mut lo, mut hi) = (0, bytes.len() / 4);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if u16_at(bytes, 2 * mid) <= value {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// Returns the bits of a word covering the values `lo..hi` relative to it.
+fn word_mask(lo: u32, hi: u32) -> u64 {
+    if hi - lo == 64 {
+        !0
+    } else {
+        ((1 << (hi - lo)) - 1) << lo
+    }
+}
+
+/// A set operation computed between serialized containers.
+#[derive(Clone, Copy)]
+enum Operation {
+    Union,
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+impl Operation {
+    /// Returns whether a value present in `lhs` and in `rhs` as given is part
+    /// of the result.
+    fn keep(self) -> fn(bool, bool) -> bool {
+        match self {
+            Operation::Union => |a, b| a || b,
+            Operation::Intersection => |a, b| a && b,
+            Operation::Difference => |a, b| a && !b,
+            Operation::SymmetricDifference => |a, b| a != b,
+        }
+    }
+
+    fn word(self, lhs: u64, rhs: u64) -> u64 {
+        match self {
+            Operation::Union => lhs | rhs,
+            Operation::Intersection => lhs & rhs,
+            Operation::Difference => lhs & !rhs,
+            Operation::SymmetricDifference => lhs ^ rhs,
+        }
+    }
+}
+
+/// Returns `data[start..start + len]`, failing if `data` is too short.
+fn slice(data: &[u8], start: usize, len: usize) -> io::Result<&[u8]> {
+    if start.checked_add(len).is_some_and(|end| end <= data.len()) {
+        Ok(&data[start..start + len])
+    } else {
+        Err(invalid_data("truncated data"))
+    }
+}
+
+/// A serialized container, borrowed from the buffer.
+#[derive(Clone, Copy)]
+enum ContainerView<'a> {
+    // sorted 16-bit integers
+    Array(&'a [u8]),
+    // 2**16 bitmap as 1024 64-bit words
+    Bitmap(&'a [u8]),
+    // (start, length) pairs
+    Run(&'a [u8]),
+}
+
+impl<'a> ContainerView<'a> {
+    fn contains(&self, value: u16) -> bool {
+        match *self {
+            ContainerView::Array(bytes) => search(bytes, value).is_ok(),
+            ContainerView::Bitmap(bytes) => {
+                bytes[value as usize / 8] & (1 << (value % 8)) != 0
+            }
+            ContainerView::Run(bytes) => {
+                let lo = search_runs(bytes, value);
+                lo > 0 && {
+                    let start = u16_at(bytes, 2 * (lo - 1)) as u32;
+                    let length = u16_at(bytes, 2 * (lo - 1) + 1) as u32;
+                    value as u32 <= start + length
+                }
+            }
+        }
+    }
+
+    /// Returns the number of values lower than or equal to `value`, given the
+    /// cardinality `len` of the container.
+    fn rank(&self, value: u16, len: usize) -> usize {
+        match *self {
+            ContainerView::Array(bytes) => match search(bytes, value) {
+                Ok(i) => i + 1,
+                Err(i) => i,
+            },
+            ContainerView::Bitmap(bytes) => {
+                let (index, bit) = (value as usize / 64, value as usize % 64);
+                let mut rank = (0..index)
+                    .map(|i| u64_at(bytes, i).count_ones() as usize)
+                    .sum::<usize>();
+                let mask = if bit == 63 { !0 } else { (1 << (bit + 1)) - 1 };
+                rank += (u64_at(bytes, index) & mask).count_ones() as usize;
+                rank
+            }
+            ContainerView::Run(bytes) => {
+                let run_count = bytes.len() / 4;
+                let i = search_runs(bytes, value);
+                let run_len = |i| u16_at(bytes, 2 * i + 1) as usize + 1;
+                // count the whole runs on the side holding fewer of them
+                let rank = if i <= run_count / 2 {
+                    (0..i).map(run_len).sum()
+                } else {
+                    len - (i..run_count).map(run_len).sum::<usize>()
+                };
+                if i == 0 {
+                    return rank;
+                }
+                let start = u16_at(bytes, 2 * (i - 1)) as usize;
+                let excess = (start + run_len(i - 1)).saturating_sub(value as usize + 1);
+                rank - excess
+            }
+        }
+    }
+
+    /// Returns the values of the container as sorted, non-overlapping and
+    /// non-adjacent half-open intervals `[lo, hi)`.
+    fn intervals(self) -> Box<dyn Iterator<Item = (u32, u32)> + 'a> {
+        if let ContainerView::Run(bytes) = self {
+            return Box::new((0..bytes.len() / 4).map(move |i| {
+                let start = u16_at(bytes, 2 * i) as u32;
+                (start, start + u16_at(bytes, 2 * i + 1) as u32 + 1)
+            }));
+        }
+        let mut values = Values::new(self).peekable();
+        Box::new(iter::from_fn(move || {
+            let lo = values.next()? as u32;
+            let mut hi = lo + 1;
+            while values.peek().is_some_and(|&val| val as u32 == hi) {
+                values.next();
+                hi += 1;
+            }
+            Some((lo, hi))
+        }))
+    }
+
+    /// Returns the 1024 words of the container as a bitmap.
+    fn words(self) -> Box<dyn Iterator<Item = u64> + 'a> {
+        if let ContainerView::Bitmap(bytes) = self {
+            return Box::new((0..WORD_COUNT).map(move |i| u64_at(bytes, i)));
+        }
+        let mut intervals = self.intervals().peekable();
+        Box::new((0..WORD_COUNT as u32).map(move |i| {
+            let (lo, hi) = (64 * i, 64 * i + 64);
+            let mut word = 0;
+            while let Some(&(start, end)) = intervals.peek() {
+                if start >= hi {
+                    break;
+                }
+                word |= word_mask(cmp::max(start, lo) - lo, cmp::min(end, hi) - lo);
+                if end > hi {
+                    // the interval goes on in the next word
+                    break;
+                }
+                intervals.next();
+            }
+            word
+        }))
+    }
+
+    /// Returns the values of an array container in ascending order.
+    fn array_values(bytes: &'a [u8]) -> impl Iterator<Item = u16> + 'a {
+        (0..bytes.len() / 2).map(move |i| u16_at(bytes, i))
+    }
+
+    /// Combines two serialized containers with `op`, reading them in place.
+    ///
+    /// Array containers are filtered value by value when the result is a
+    /// subset of them, bitmap containers are combined word by word, reading
+    /// the other side as words when it is not a bitmap, and the other
+    /// pairings as runs.
+    fn combine(self, other: ContainerView<'a>, op: Operation) -> Container {
+        let mut container = match (op, self, other) {
+            (Operation::Intersection, ContainerView::Array(bytes), container) |
+            (Operation::Intersection, container, ContainerView::Array(bytes)) => {
+                Container::Sparse(ContainerView::array_values(bytes)
+                    .filter(|&val| container.contains(val))
+                    .collect())
+            }
+            (Operation::Difference, ContainerView::Array(bytes), container) => {
+                Container::Sparse(ContainerView::array_values(bytes)
+                    .filter(|&val| !container.contains(val))
+                    .collect())
+            }
+            (_, ContainerView::Bitmap(lhs), ContainerView::Bitmap(rhs)) => {
+                let mut words = Box::new([0; WORD_COUNT]);
+                for (i, word) in words.iter_mut().enumerate() {
+                    *word = op.word(u64_at(lhs, i), u64_at(rhs, i));
+                }
+                Container::Dense(BitSet::from_words(words))
+            }
+            (_, ContainerView::Bitmap(_), _) | (_, _, ContainerView::Bitmap(_)) => {
+                let mut words = Box::new([0; WORD_COUNT]);
+                let pairs = self.words().zip(other.words());
+                for (word, (lhs, rhs)) in words.iter_mut().zip(pairs) {
+                    *word = op.word(lhs, rhs);
+                }
+                Container::Dense(BitSet::from_words(words))
+            }
+            _ => {
+                Container::Run(run::combine_intervals(self.intervals(),
+                                                      other.intervals(),
+                                                      op.keep()))
+            }
+        };
+        container.normalize(&Config::default());
+        container
+    }
+
+    /// Copies the container out of the buffer.
+    fn to_container(self) -> Container {
+        let mut container = match self {
+            ContainerView::Array(bytes) => {
+                Container::Sparse(ContainerView::array_values(bytes).collect())
+            }
+            ContainerView::Bitmap(bytes) => {
+                let mut words = Box::new([0; WORD_COUNT]);
+                for (i, word) in words.iter_mut().enumerate() {
+                    *word = u64_at(bytes, i);
+                }
+                Container::Dense(BitSet::from_words(words))
+            }
+            ContainerView::Run(bytes) => {
+                Container::Run((0..bytes.len() / 4)
+                    .map(|i| (u16_at(bytes, 2 * i), u16_at(bytes, 2 * i + 1)))
+                    .collect())
+            }
+        };
+        container.normalize(&Config::default());
+        container
+    }
+}
+
+/// A read-only bitmap borrowing a buffer holding a bitmap serialized in the
+/// portable format, such as a memory-mapped file.
+///
+/// Queries are answered directly from the buffer; only the container offsets
+/// are computed when the view is created. Containers are checked to lie
+/// within the buffer and to hold as many values as their header states, so
+/// that a corrupted buffer is rejected instead of breaking later queries.
+pub struct RoaringBitMapView<'a> {
+    data: &'a [u8],
+    run_bitset: Option<&'a [u8]>,
+    // (key, cardinality - 1) pairs
+    descriptive_header: &'a [u8],
+    offsets: Vec<usize>,
+    // number of values in the containers preceding each container, followed
+    // by the cardinality of the bitmap
+    ranks: Vec<u64>,
+}
+
+impl<'a> RoaringBitMapView<'a> {
+    /// Creates a view over `data`, which must hold a bitmap written by
+    /// `RoaringBitMap::serialize_into` or another Roaring implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::{RoaringBitMap, RoaringBitMapView};
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert_range(10..20);
+    /// let mut bytes = Vec::new();
+    /// bitmap.serialize_into(&mut bytes).unwrap();
+    ///
+    /// let view = RoaringBitMapView::new(&bytes).unwrap();
+    /// assert_eq!(view.len(), 10);
+    /// assert!(view.contains(15));
+    /// ```
+    pub fn new(data: &'a [u8]) -> io::Result<RoaringBitMapView<'a>> {
+        let cookie = u32_at(slice(data, 0, 4)?, 0);
+        let no_runs = cookie == SERIAL_COOKIE_NO_RUNCONTAINER;
+        let (count, run_bitset, header_start) = if no_runs {
+            (u32_at(slice(data, 4, 4)?, 0) as usize, None, 8)
+        } else if cookie as u16 == SERIAL_COOKIE {
+            let count = (cookie >> 16) as usize + 1;
+            let run_bitset = slice(data, 4, count.div_ceil(8))?;
+            (count, Some(run_bitset), 4 + run_bitset.len())
+        } else {
+            return Err(invalid_data("unknown cookie"));
+        };
+        if count > 1 << 16 {
+            return Err(invalid_data("too many containers"));
+        }
+        let descriptive_header = slice(data, header_start, 4 * count)?;
+        for i in 1..count {
+            let (prev, key) = (u16_at(descriptive_header, 2 * (i - 1)),
+                               u16_at(descriptive_header, 2 * i));
+            if prev >= key {
+                return Err(invalid_data("keys are not sorted"));
+            }
+        }
+
+        let mut view = RoaringBitMapView {
+            data,
+            run_bitset,
+            descriptive_header,
+            offsets: Vec::with_capacity(count),
+            ranks: Vec::with_capacity(count + 1),
+        };
+        view.ranks.push(0);
+        for i in 0..count {
+            let rank = view.ranks[i] + view.container_len(i) as u64;
+            view.ranks.push(rank);
+        }
+        let mut offset = header_start + 4 * count;
+        if run_bitset.is_none() || count >= NO_OFFSET_THRESHOLD {
+            let offsets = slice(data, offset, 4 * count)?;
+            for i in 0..count {
+                view.offsets.push(u32_at(offsets, i) as usize);
+            }
+        } else {
+            // containers follow each other without any offset header
+            for i in 0..count {
+                view.offsets.push(offset);
+                offset += view.container_size(i)?;
+            }
+        }
+        for i in 0..count {
+            let size = view.container_size(i)?;
+            slice(data, view.offsets[i], size)?;
+            view.check_container(i)?;
+        }
+        Ok(view)
+    }
+
+    /// Checks that the values of a bitmap or run container match the
+    /// cardinality given in the header. Array containers hold exactly that
+    /// many values by construction.
+    fn check_container(&self, i: usize) -> io::Result<()> {
+        let len = match self.container(i) {
+            ContainerView::Array(_) => return Ok(()),
+            ContainerView::Bitmap(bytes) => {
+                (0..WORD_COUNT)
+                    .map(|index| u64_at(bytes, index).count_ones() as usize)
+                    .sum()
+            }
+            ContainerView::Run(bytes) => {
+                let mut len = 0;
+                let mut next_start = 0;
+                for i in 0..bytes.len() / 4 {
+                    let start = u16_at(bytes, 2 * i) as usize;
+                    let length = u16_at(bytes, 2 * i + 1) as usize;
+                    if start + length > u16::MAX as usize {
+                        return Err(invalid_data("run exceeds the container"));
+                    }
+                    if start < next_start {
+                        return Err(invalid_data("runs are not sorted"));
+                    }
+                    len += length + 1;
+                    next_start = start + length + 1;
+                }
+                len
+            }
+        };
+        if len != self.container_len(i) {
+            return Err(invalid_data("container cardinality mismatch"));
+        }
+        Ok(())
+    }
+
+    fn is_run(&self, i: usize) -> bool {
+        self.run_bitset
+            .is_some_and(|run_bitset| run_bitset[i / 8] & (1 << (i % 8)) != 0)
+    }
+
+    fn key(&self, i: usize) -> u16 {
+        u16_at(self.descriptive_header, 2 * i)
+    }
+
+    fn container_len(&self, i: usize) -> usize {
+        u16_at(self.descriptive_header, 2 * i + 1) as usize + 1
+    }
+
+    fn container_size(&self, i: usize) -> io::Result<usize> {
+        if self.is_run(i) {
+            let run_count = slice(self.data, self.offsets[i], 2)?;
+            Ok(2 + 4 * u16_at(run_count, 0) as usize)
+        } else if self.container_len(i) <= ARRAY_CONTAINER_MAX_LEN {
+            Ok(2 * self.container_len(i))
+        } else {
+            Ok(BITMAP_CONTAINER_SIZE_IN_BYTES)
+        }
+    }
+
+    fn container(&self, i: usize) -> ContainerView<'a> {
+        let offset = self.offsets[i];
+        let data = self.data;
+        if self.is_run(i) {
+            let run_count = u16_at(&data[offset..], 0) as usize;
+            ContainerView::Run(&data[offset + 2..offset + 2 + 4 * run_count])
+        } else if self.container_len(i) <= ARRAY_CONTAINER_MAX_LEN {
+            ContainerView::Array(&data[offset..offset + 2 * self.container_len(i)])
+        } else {
+            ContainerView::Bitmap(&data[offset..offset + BITMAP_CONTAINER_SIZE_IN_BYTES])
+        }
+    }
+
+    fn search(&self, key: u16) -> Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.offsets.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.key(mid).cmp(&key) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranks[self.offsets.len()] as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        let (key, val) = key_val_pair(value);
+        match self.search(key) {
+            Ok(i) => self.container(i).contains(val),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the number of values lower than or equal to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::{RoaringBitMap, RoaringBitMapView};
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(3);
+    /// bitmap.insert(70000);
+    /// let mut bytes = Vec::new();
+    /// bitmap.serialize_into(&mut bytes).unwrap();
+    ///
+    /// let view = RoaringBitMapView::new(&bytes).unwrap();
+    /// assert_eq!(view.rank(3), 1);
+    /// assert_eq!(view.rank(80000), 2);
+    /// ```
+    pub fn rank(&self, value: u32) -> u64 {
+        let (key, val) = key_val_pair(value);
+        match self.search(key) {
+            Ok(i) => {
+                let rank = self.container(i).rank(val, self.container_len(i));
+                self.ranks[i] + rank as u64
+            }
+            Err(i) => self.ranks[i],
+        }
+    }
+
+    /// Returns an iterator over the values of the view in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::{RoaringBitMap, RoaringBitMapView};
+    ///
+    /// let mut bitmap = RoaringBitMap::new();
+    /// bitmap.insert(70000);
+    /// bitmap.insert(3);
+    /// let mut bytes = Vec::new();
+    /// bitmap.serialize_into(&mut bytes).unwrap();
+    ///
+    /// let view = RoaringBitMapView::new(&bytes).unwrap();
+    /// assert_eq!(view.iter().collect::<Vec<u32>>(), vec![3, 70000]);
+    /// ```
+    pub fn iter<'b>(&'b self) -> ViewIter<'a, 'b> {
+        ViewIter {
+            view: self,
+            index: 0,
+            values: None,
+            len: self.len(),
+        }
+    }
+
+    /// Copies the view into a `RoaringBitMap`.
+    pub fn to_bitmap(&self) -> RoaringBitMap {
+        RoaringBitMap {
+            keys: (0..self.offsets.len()).map(|i| self.key(i)).collect(),
+            containers: (0..self.offsets.len())
+                .map(|i| Box::new(self.container(i).to_container()))
+                .collect(),
+            config: Config::default(),
+        }
+    }
+
+    /// Walks the keys of `self` and `other` in lockstep, building the result
+    /// of `op` without copying the input containers.
+    ///
+    /// Containers whose key only appears in `self` (resp. `other`) are copied
+    /// into the result according to `keep_lhs` (resp. `keep_rhs`), and
+    /// containers sharing a key are combined in place.
+    fn merge(&self, other: &RoaringBitMapView, keep_lhs: bool, keep_rhs: bool,
+             op: Operation) -> RoaringBitMap {
+        let mut result = RoaringBitMap::new();
+        let (lhs_count, rhs_count) = (self.offsets.len(), other.offsets.len());
+        let (mut i, mut j) = (0, 0);
+        while i < lhs_count || j < rhs_count {
+            let order = if i == lhs_count {
+                Ordering::Greater
+            } else if j == rhs_count {
+                Ordering::Less
+            } else {
+                self.key(i).cmp(&other.key(j))
+            };
+            let (key, container) = match order {
+                Ordering::Less => {
+                    i += 1;
+                    if !keep_lhs {
+                        continue;
+                    }
+                    (self.key(i - 1), self.container(i - 1).to_container())
+                }
+                Ordering::Greater => {
+                    j += 1;
+                    if !keep_rhs {
+                        continue;
+                    }
+                    (other.key(j - 1), other.container(j - 1).to_container())
+                }
+                Ordering::Equal => {
+                    let container = self.container(i).combine(other.container(j), op);
+                    i += 1;
+                    j += 1;
+                    (self.key(i - 1), container)
+                }
+            };
+            if container.len() > 0 {
+                result.keys.push(key);
+                result.containers.push(Box::new(container));
+            }
+        }
+        result
+    }
+
+    /// Returns a new bitmap holding the values present in `self` or `other`.
+    pub fn union(&self, other: &RoaringBitMapView) -> RoaringBitMap {
+        self.merge(other, true, true, Operation::Union)
+    }
+
+    /// Returns a new bitmap holding the values present in both `self` and
+    /// `other`. Only the containers whose keys appear in both are read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roaring_bitmap::{RoaringBitMap, RoaringBitMapView};
+    ///
+    /// let mut a = RoaringBitMap::new();
+    /// a.insert_range(0..100);
+    /// let mut b = RoaringBitMap::new();
+    /// b.insert_range(50..150);
+    /// let (mut a_bytes, mut b_bytes) = (Vec::new(), Vec::new());
+    /// a.serialize_into(&mut a_bytes).unwrap();
+    /// b.serialize_into(&mut b_bytes).unwrap();
+    ///
+    /// let a = RoaringBitMapView::new(&a_bytes).unwrap();
+    /// let b = RoaringBitMapView::new(&b_bytes).unwrap();
+    /// assert_eq!(a.intersection(&b).len(), 50);
+    /// ```
+    pub fn intersection(&self, other: &RoaringBitMapView) -> RoaringBitMap {
+        self.merge(other, false, false, Operation::Intersection)
+    }
+
+    /// Returns a new bitmap holding the values present in `self` but not in
+    /// `other`.
+    pub fn difference(&self, other: &RoaringBitMapView) -> RoaringBitMap {
+        self.merge(other, true, false, Operation::Difference)
+    }
+
+    /// Returns a new bitmap holding the values present in exactly one of
+    /// `self` and `other`.
+    pub fn symmetric_difference(&self, other: &RoaringBitMapView) -> RoaringBitMap {
+        self.merge(other, true, true, Operation::SymmetricDifference)
+    }
+}
+
+/// Position of an iterator within a serialized container.
+enum Values<'a> {
+    // bytes and index of the next value
+    Array(&'a [u8], usize),
+    // bytes, index of the current word and its bits left to yield
+    Bitmap(&'a [u8], usize, u64),
+    // bytes, index of the current run and next value
+    Run(&'a [u8], usize, u32),
+}
+
+impl<'a> Values<'a> {
+    fn new(container: ContainerView<'a>) -> Values<'a> {
+        match container {
+            ContainerView::Array(bytes) => Values::Array(bytes, 0),
+            ContainerView::Bitmap(bytes) => Values::Bitmap(bytes, 0, u64_at(bytes, 0)),
+            ContainerView::Run(bytes) => {
+                let start = if bytes.is_empty() { 0 } else { u16_at(bytes, 0) as u32 };
+                Values::Run(bytes, 0, start)
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Values<'a> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        match *self {
+            Values::Array(bytes, ref mut i) => {
+                if *i < bytes.len() / 2 {
+                    *i += 1;
+                    Some(u16_at(bytes, *i - 1))
+                } else {
+                    None
+                }
+            }
+            Values::Bitmap(bytes, ref mut i, ref mut word) => {
+                while *word == 0 {
+                    *i += 1;
+                    if *i == bytes.len() / 8 {
+                        return None;
+                    }
+                    *word = u64_at(bytes, *i);
+                }
+                let val = *i * 64 + word.trailing_zeros() as usize;
+                // clear the lowest set bit
+                *word &= *word - 1;
+                Some(val as u16)
+            }
+            Values::Run(bytes, ref mut i, ref mut val) => {
+                if *i == bytes.len() / 4 {
+                    return None;
+                }
+                let current = *val;
+                let end = u16_at(bytes, 2 * *i) as u32 + u16_at(bytes, 2 * *i + 1) as u32;
+                if current >= end {
+                    *i += 1;
+                    if *i < bytes.len() / 4 {
+                        *val = u16_at(bytes, 2 * *i) as u32;
+                    }
+                } else {
+                    *val += 1;
+                }
+                Some(current as u16)
+            }
+        }
+    }
+}
+
+/// An iterator over the values of a `RoaringBitMapView` in ascending order.
+pub struct ViewIter<'a: 'b, 'b> {
+    view: &'b RoaringBitMapView<'a>,
+    // index of the next container to start iterating from
+    index: usize,
+    values: Option<Values<'a>>,
+    len: usize,
+}
+
+impl<'a, 'b> Iterator for ViewIter<'a, 'b> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        loop {
+            if let Some(ref mut values) = self.values {
+                if let Some(val) = values.next() {
+                    self.len -= 1;
+                    let key = self.view.key(self.index - 1);
+                    return Some(((key as u32) << 16) | val as u32);
+                }
+            }
+            if self.index == self.view.offsets.len() {
+                self.values = None;
+                return None;
+            }
+            self.values = Some(Values::new(self.view.container(self.index)));
+            self.index += 1;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, 'b> ExactSizeIterator for ViewIter<'a, 'b> {}
+
+impl<'a, 'b> IntoIterator for &'b RoaringBitMapView<'a> {
+    type Item = u32;
+    type IntoIter = ViewIter<'a, 'b>;
+
+    fn into_iter(self) -> ViewIter<'a, 'b> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoaringBitMapView;
+    use RoaringBitMap;
+
+    static BITMAP_WITH_RUNS: &[u8] =
+        include_bytes!("../tests/data/bitmapwithruns.bin");
+
+    fn serialize(bitmap: &RoaringBitMap) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bitmap.serialize_into(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Returns bitmaps with few and many containers of every kind.
+    fn bitmaps() -> Vec<RoaringBitMap> {
+        let mut small = RoaringBitMap::new();
+        small.insert_range(10..20);
+        small.validate().unwrap();
+        small.insert(70000);
+        small.validate().unwrap();
+        small.insert_range(200000..210000);
+        small.validate().unwrap();
+        small.run_optimize();
+        small.validate().unwrap();
+
+        let mut large = RoaringBitMap::deserialize_from(BITMAP_WITH_RUNS).unwrap();
+        large.insert_range(0..5);
+        large.validate().unwrap();
+        large.insert_range(1 << 20..(1 << 20) + 5000);
+        large.validate().unwrap();
+        large.remove_range(1 << 20..(1 << 20) + 5000);
+        large.validate().unwrap();
+        large.remove_run_compression();
+        large.validate().unwrap();
+        large.insert_range(1000..2000);
+        large.validate().unwrap();
+        large.insert(u32::MAX);
+        large.validate().unwrap();
+
+        vec![RoaringBitMap::new(), small, large,
+             RoaringBitMap::deserialize_from(BITMAP_WITH_RUNS).unwrap()]
+    }
+
+    #[test]
+    fn test_queries() {
+        for bitmap in bitmaps() {
+            let bytes = serialize(&bitmap);
+            let view = RoaringBitMapView::new(&bytes).unwrap();
+            let values = bitmap.iter().collect::<Vec<u32>>();
+            assert_eq!(view.len(), bitmap.len());
+            assert_eq!(view.is_empty(), bitmap.is_empty());
+            assert_eq!(view.iter().len(), values.len());
+            assert_eq!(view.iter().collect::<Vec<u32>>(), values);
+            assert_eq!((&view).into_iter().collect::<Vec<u32>>(), values);
+            assert_eq!(view.to_bitmap().iter().collect::<Vec<u32>>(), values);
+            let sample = values.iter().step_by(89).cloned();
+            for value in (0..1 << 21).step_by(97).chain(sample) {
+                assert_eq!(view.contains(value), bitmap.contains(value));
+                assert_eq!(view.rank(value), bitmap.rank(value));
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let bitmaps = bitmaps();
+        let bytes = bitmaps.iter().map(serialize).collect::<Vec<Vec<u8>>>();
+        for (lhs, lhs_bytes) in bitmaps.iter().zip(bytes.iter()) {
+            let lhs_view = RoaringBitMapView::new(lhs_bytes).unwrap();
+            for (rhs, rhs_bytes) in bitmaps.iter().zip(bytes.iter()) {
+                let rhs_view = RoaringBitMapView::new(rhs_bytes).unwrap();
+                let check = |result: RoaringBitMap, expected: RoaringBitMap| {
+                    assert_eq!(result.iter().collect::<Vec<u32>>(),
+                               expected.iter().collect::<Vec<u32>>());
+                };
+                check(lhs_view.union(&rhs_view), lhs.union(rhs));
+                check(lhs_view.intersection(&rhs_view), lhs.intersection(rhs));
+                check(lhs_view.difference(&rhs_view), lhs.difference(rhs));
+                check(lhs_view.symmetric_difference(&rhs_view),
+                      lhs.symmetric_difference(rhs));
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert!(RoaringBitMapView::new(&[]).is_err());
+        assert!(RoaringBitMapView::new(&[0, 0, 0, 0]).is_err());
+        for len in 0..BITMAP_WITH_RUNS.len() {
+            assert!(RoaringBitMapView::new(&BITMAP_WITH_RUNS[..len]).is_err());
+        }
+        let mut bitmap = RoaringBitMap::new();
+        bitmap.insert_range(10..20);
+        bitmap.validate().unwrap();
+        let bytes = serialize(&bitmap);
+        for len in 0..bytes.len() {
+            assert!(RoaringBitMapView::new(&bytes[..len]).is_err());
+        }
+
+        // containers disagreeing with their header cardinality
+        let mut bitmap = RoaringBitMap::new();
+        for value in (0..10000).step_by(2) {
+            bitmap.insert(value);
+            bitmap.validate().unwrap();
+        }
+        let mut bytes = serialize(&bitmap);
+        let last = bytes.len() - 1;
+        bytes[last] = 0xff;
+        assert!(RoaringBitMapView::new(&bytes).is_err());
+        let mut runs = RoaringBitMap::new();
+        runs.insert_range(10..20);
+        runs.validate().unwrap();
+        runs.run_optimize();
+        runs.validate().unwrap();
+        let mut bytes = serialize(&runs);
+        let last = bytes.len() - 2;
+        bytes[last] += 1;
+        assert!(RoaringBitMapView::new(&bytes).is_err());
+        bytes[last] = 0xff;
+        assert!(RoaringBitMapView::new(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_corrupted_data() {
+        // any single corrupted byte is either rejected or yields a view that
+        // can be queried without panicking
+        let mut bitmap = RoaringBitMap::new();
+        bitmap.insert_range(10..20);
+        bitmap.validate().unwrap();
+        bitmap.run_optimize();
+        bitmap.validate().unwrap();
+        for value in (70000..80000).step_by(2).chain(vec![200000, 200005]) {
+            bitmap.insert(value);
+            bitmap.validate().unwrap();
+        }
+        let bytes = serialize(&bitmap);
+        for i in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 0xff;
+            let view = match RoaringBitMapView::new(&corrupted) {
+                Ok(view) => view,
+                Err(_) => continue,
+            };
+            assert_eq!(view.iter().count(), view.len());
+            for value in (0..1 << 18).step_by(997) {
+                assert!(view.rank(value) <= view.len() as u64);
+            }
+            view.union(&view).validate().unwrap();
+            view.difference(&view).validate().unwrap();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Checks that set operations between views read the input containers in
+//! place. The counting allocator lives in its own test binary so that it does
+//! not affect the other tests.
+
+extern crate roaring_bitmap;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use roaring_bitmap::{RoaringBitMap, RoaringBitMapView};
+
+/// Counts the bytes allocated by each thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| {
+            allocated.set(allocated.get() + layout.size())
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocated() -> usize {
+    ALLOCATED.with(|allocated| allocated.get())
+}
+
+fn serialize(bitmap: &RoaringBitMap) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bitmap.serialize_into(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn test_set_operations_in_place() {
+    // 8 bitmap containers intersected with a few values of each
+    let mut dense = RoaringBitMap::new();
+    let mut sparse = RoaringBitMap::new();
+    for key in 0..8 {
+        for val in (0..1 << 16).step_by(2) {
+            dense.insert(key << 16 | val);
+            dense.validate().unwrap();
+        }
+        sparse.insert(key << 16 | 10);
+        sparse.validate().unwrap();
+        sparse.insert(key << 16 | 11);
+        sparse.validate().unwrap();
+    }
+    let (dense_bytes, sparse_bytes) = (serialize(&dense), serialize(&sparse));
+    let dense_view = RoaringBitMapView::new(&dense_bytes).unwrap();
+    let sparse_view = RoaringBitMapView::new(&sparse_bytes).unwrap();
+
+    let before = allocated();
+    let intersection = dense_view.intersection(&sparse_view);
+    let difference = sparse_view.difference(&dense_view);
+    // copying out a single bitmap container would take 8192 bytes
+    assert!(allocated() - before < 8192);
+    intersection.validate().unwrap();
+    difference.validate().unwrap();
+    assert_eq!(intersection.len(), 8);
+    assert!(intersection.iter().all(|value| value % 2 == 0));
+    assert_eq!(difference.len(), 8);
+    assert!(difference.iter().all(|value| value % 2 == 1));
+}